@@ -0,0 +1,13 @@
+-- Add up migration script here
+CREATE TABLE `gpt_usage` (
+    `id`                INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    `feed`              TEXT    NOT NULL,
+    `model`             TEXT    NOT NULL,
+    `prompt_tokens`     INTEGER NOT NULL,
+    `completion_tokens` INTEGER NOT NULL,
+    `latency_ms`        INTEGER NOT NULL,
+    `outcome`           TEXT    NOT NULL,
+    `cost`              REAL    NOT NULL,
+    `created_at`        INTEGER NOT NULL
+);
+CREATE INDEX `idx_gpt_usage_created_at` ON `gpt_usage`(`created_at`);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE `torrent_info` DROP COLUMN `kind`;
+ALTER TABLE `torrent_info` DROP COLUMN `episode_end`;
+ALTER TABLE `tmdb_info` DROP COLUMN `media_type`;
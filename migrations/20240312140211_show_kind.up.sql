@@ -0,0 +1,4 @@
+-- Add up migration script here
+ALTER TABLE `torrent_info` ADD COLUMN `kind` TEXT NOT NULL DEFAULT 'episode';
+ALTER TABLE `torrent_info` ADD COLUMN `episode_end` INTEGER;
+ALTER TABLE `tmdb_info` ADD COLUMN `media_type` TEXT NOT NULL DEFAULT 'tv';
@@ -0,0 +1,8 @@
+-- Add up migration script here
+CREATE TABLE `tmdb_season` (
+    `tmdb_id`       INTEGER NOT NULL,
+    `season`        INTEGER NOT NULL,
+    `episode_count` INTEGER NOT NULL,
+    `updated_at`    INTEGER NOT NULL,
+    PRIMARY KEY (`tmdb_id`, `season`)
+);
@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE `tmdb_info` DROP COLUMN `updated_at`;
@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- `tmdb_id` = 0 的行表示 TMDB 上没有找到
+ALTER TABLE `tmdb_info` ADD COLUMN `updated_at` INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE `tmdb_info` DROP COLUMN `confidence`;
+ALTER TABLE `tmdb_info` DROP COLUMN `candidate_id`;
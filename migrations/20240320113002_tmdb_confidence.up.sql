@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- 置信度过低的结果以 `tmdb_id` = 0 保存，`candidate_id` 为最佳候选
+ALTER TABLE `tmdb_info` ADD COLUMN `confidence` REAL NOT NULL DEFAULT 1.0;
+ALTER TABLE `tmdb_info` ADD COLUMN `candidate_id` INTEGER NOT NULL DEFAULT 0;
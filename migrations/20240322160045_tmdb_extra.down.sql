@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS `idx_tmdb_info_tmdb_id`;
+ALTER TABLE `tmdb_info` DROP COLUMN `original_name`;
+ALTER TABLE `tmdb_info` DROP COLUMN `poster_path`;
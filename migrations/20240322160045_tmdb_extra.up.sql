@@ -0,0 +1,4 @@
+-- Add up migration script here
+ALTER TABLE `tmdb_info` ADD COLUMN `original_name` TEXT NOT NULL DEFAULT '';
+ALTER TABLE `tmdb_info` ADD COLUMN `poster_path` TEXT;
+CREATE INDEX `idx_tmdb_info_tmdb_id` ON `tmdb_info`(`tmdb_id`);
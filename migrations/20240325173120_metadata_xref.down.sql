@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE `tmdb_info` DROP COLUMN `bangumi_id`;
+ALTER TABLE `tmdb_info` DROP COLUMN `anilist_id`;
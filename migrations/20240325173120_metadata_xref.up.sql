@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- 其他元数据源的交叉引用 id，0 表示没有匹配
+ALTER TABLE `tmdb_info` ADD COLUMN `bangumi_id` INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE `tmdb_info` ADD COLUMN `anilist_id` INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- 缓存按语言区分，之前都是中文
+ALTER TABLE `tmdb_info` ADD COLUMN `language` TEXT NOT NULL DEFAULT 'zh-CN';
@@ -0,0 +1,5 @@
+-- Add down migration script here
+DROP INDEX `idx_series_tmdb`;
+ALTER TABLE `series` DROP COLUMN `tmdb_id`;
+ALTER TABLE `series` DROP COLUMN `feed`;
+ALTER TABLE `series` DROP COLUMN `quality`;
@@ -0,0 +1,6 @@
+-- Add up migration script here
+-- 按 TMDB id + 季 + 集去重，没有 TMDB id 时按剧名
+ALTER TABLE `series` ADD COLUMN `tmdb_id` INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE `series` ADD COLUMN `feed` TEXT NOT NULL DEFAULT '';
+ALTER TABLE `series` ADD COLUMN `quality` INTEGER NOT NULL DEFAULT 0;
+CREATE INDEX `idx_series_tmdb` ON `series` (`tmdb_id`, `series_season`, `series_episode`);
@@ -0,0 +1,23 @@
+-- Add up migration script here
+-- 等待期内的候选版本，到期后同一集只下载得分最高的
+CREATE TABLE `pending` (
+    `id`            INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    `feed`          TEXT    NOT NULL,
+    `guid`          TEXT    NOT NULL,
+    `title`         TEXT    NOT NULL,
+    `link`          TEXT    NOT NULL,
+    `enclosure`     TEXT    NOT NULL,
+    `show`          TEXT    NOT NULL,
+    `year`          INTEGER NOT NULL,
+    `tmdb_id`       INTEGER NOT NULL,
+    `kind`          TEXT    NOT NULL,
+    `season`        INTEGER NOT NULL,
+    `episode`       INTEGER NOT NULL,
+    `episode_end`   INTEGER,
+    `fansub`        TEXT    NOT NULL,
+    `resolution`    TEXT    NOT NULL,
+    `language`      TEXT    NOT NULL,
+    `score`         INTEGER NOT NULL,
+    `created_at`    INTEGER NOT NULL
+);
+CREATE INDEX `idx_pending_feed` ON `pending` (`feed`);
@@ -0,0 +1,6 @@
+-- Add down migration script here
+ALTER TABLE `series` DROP COLUMN `torrent_id`;
+ALTER TABLE `series` DROP COLUMN `fansub`;
+ALTER TABLE `series` DROP COLUMN `version`;
+ALTER TABLE `torrent_info` DROP COLUMN `feed`;
+ALTER TABLE `torrent_info` DROP COLUMN `replaces`;
@@ -0,0 +1,7 @@
+-- Add up migration script here
+-- 替换已下载版本需要找到旧种子
+ALTER TABLE `series` ADD COLUMN `torrent_id` INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE `series` ADD COLUMN `fansub` TEXT NOT NULL DEFAULT '';
+ALTER TABLE `series` ADD COLUMN `version` INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE `torrent_info` ADD COLUMN `feed` TEXT NOT NULL DEFAULT '';
+ALTER TABLE `torrent_info` ADD COLUMN `replaces` INTEGER;
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX `torrent_info_hash`;
+ALTER TABLE `torrent_info` DROP COLUMN `hash`;
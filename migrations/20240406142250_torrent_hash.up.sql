@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- 非 qb 的下载器不能改种子名，靠 infohash 找到种子
+ALTER TABLE `torrent_info` ADD COLUMN `hash` TEXT;
+CREATE INDEX `torrent_info_hash` ON `torrent_info` (`hash`);
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- 轮询完成的种子时跳过已链接的。之前的种子不知道是否下载完，交给轮询和对账处理，
+-- 重复链接同一个文件会跳过
+ALTER TABLE `torrent_info` ADD COLUMN `linked` INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,7 @@
+-- Add down migration script here
+DROP INDEX `torrent_info_state`;
+ALTER TABLE `torrent_info` ADD COLUMN `linked` INTEGER NOT NULL DEFAULT 0;
+UPDATE `torrent_info` SET `linked` = 1 WHERE `state` = 'linked';
+ALTER TABLE `torrent_info` DROP COLUMN `state`;
+ALTER TABLE `torrent_info` DROP COLUMN `error`;
+ALTER TABLE `torrent_info` DROP COLUMN `updated_at`;
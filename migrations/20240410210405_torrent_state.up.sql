@@ -0,0 +1,8 @@
+-- Add up migration script here
+-- 种子状态：added, downloading, completed, linked, failed, removed, replaced
+ALTER TABLE `torrent_info` ADD COLUMN `state` TEXT NOT NULL DEFAULT 'added';
+ALTER TABLE `torrent_info` ADD COLUMN `error` TEXT;
+ALTER TABLE `torrent_info` ADD COLUMN `updated_at` INTEGER NOT NULL DEFAULT 0;
+UPDATE `torrent_info` SET `state` = 'linked' WHERE `linked` = 1;
+ALTER TABLE `torrent_info` DROP COLUMN `linked`;
+CREATE INDEX `torrent_info_state` ON `torrent_info` (`state`);
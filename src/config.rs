@@ -1,5 +1,6 @@
-use std::{fmt, path::PathBuf};
+use std::{collections::HashMap, fmt, path::PathBuf};
 
+use crate::metadata::MediaType;
 use serde::Deserialize;
 
 #[derive(Deserialize)]
@@ -13,25 +14,88 @@ pub struct Config {
 
     pub tmdb_secret: String,
 
+    #[serde(default)]
+    pub tmdb: TmdbConfig,
+
+    #[serde(default)]
+    pub metadata: MetadataConfig,
+
     pub link_to: PathBuf,
 
+    /// 剧场版的链接目录，默认和 `link_to` 相同
+    pub movie_link_to: Option<PathBuf>,
+
     /// request timeout
     #[serde(default = "default_timeout")]
     pub timeout_s: u64,
 
+    /// 轮询下载器中已完成种子的间隔，设置后不依赖 qb 的完成回调
+    pub completion_poll_s: Option<u64>,
+
+    /// 对账的间隔，补上漏掉的链接并标记已删除的种子，0 为关闭
+    #[serde(default = "default_reconcile")]
+    pub reconcile_s: u64,
+
     pub email: Option<Email>,
 
     pub gpt: GptConfig,
 
-    pub qb: QbConfig,
+    /// 旧的 qb 配置，没有 `downloader` 时使用
+    pub qb: Option<QbConfig>,
+
+    /// 下载器，qbittorrent / transmission / deluge / aria2
+    pub downloader: Option<DownloaderConfig>,
+
+    /// 多个下载器，feed 里用 `client = "名字"` 选择
+    #[serde(default, rename = "client")]
+    pub clients: Vec<NamedClient>,
 
     pub default: FeedBase,
 
     #[serde(default)]
     pub feed: Vec<Feed>,
+
+    /// 按剧名手动修正季和集数
+    #[serde(default, rename = "show")]
+    pub shows: Vec<ShowOverride>,
 }
 impl Config {
+    /// 按名字找下载器，返回名字和配置。不指定时用 `[downloader]` 或 `[qb]`，都没有时用第一个 `[[client]]`
+    pub fn client(&self, name: Option<&str>) -> anyhow::Result<(&str, &DownloaderConfig)> {
+        use anyhow::Context;
+        match name {
+            Some(name) => self
+                .clients
+                .iter()
+                .find(|c| c.name == name)
+                .map(|c| (c.name.as_str(), &c.config))
+                .with_context(|| format!("no client named {name} in config")),
+            None => match &self.downloader {
+                Some(d) => Ok(("", d)),
+                None => self
+                    .clients
+                    .first()
+                    .map(|c| (c.name.as_str(), &c.config))
+                    .context("no [downloader], [qb] or [[client]] in config"),
+            },
+        }
+    }
+
+    /// feed 使用的下载器，feed 不存在时用默认的
+    pub fn feed_client(&self, feed: &str) -> anyhow::Result<(&str, &DownloaderConfig)> {
+        let name = self
+            .feed
+            .iter()
+            .find(|f| f.name() == feed)
+            .and_then(|f| f.base().client.as_deref())
+            .or(self.default.client.as_deref());
+        self.client(name)
+    }
+
     pub fn update_default(&mut self) {
+        if self.downloader.is_none() {
+            self.downloader = self.qb.clone().map(DownloaderConfig::Qbittorrent);
+        }
         let d = &self.default;
         for feed in self.feed.iter_mut() {
             let b = feed.base_mut();
@@ -42,21 +106,248 @@ impl Config {
             b.tags = b.tags.take().or_else(|| d.tags.clone());
             b.auto_torrent_management = b.auto_torrent_management.or(d.auto_torrent_management);
             b.ratio_limit = b.ratio_limit.or(d.ratio_limit);
+            b.paused = b.paused.or(d.paused);
+            b.skip_checking = b.skip_checking.or(d.skip_checking);
+            b.sequential_download = b.sequential_download.or(d.sequential_download);
+            b.first_last_piece_prio = b.first_last_piece_prio.or(d.first_last_piece_prio);
+            b.up_limit_kb = b.up_limit_kb.or(d.up_limit_kb);
+            b.dl_limit_kb = b.dl_limit_kb.or(d.dl_limit_kb);
+            b.seeding_time_limit_min = b.seeding_time_limit_min.or(d.seeding_time_limit_min);
+            b.download_path = b.download_path.take().or_else(|| d.download_path.clone());
             b.filters = b.filters.take().or_else(|| d.filters.clone());
             b.not_filters = b.not_filters.take().or_else(|| d.not_filters.clone());
+            b.anime = b.anime.or(d.anime);
+            b.language = b.language.take().or_else(|| d.language.clone());
+            b.fallback_language = b
+                .fallback_language
+                .take()
+                .or_else(|| d.fallback_language.clone());
+            b.region = b.region.take().or_else(|| d.region.clone());
+            b.include_adult = b.include_adult.or(d.include_adult);
+            b.naming = b.naming.or(d.naming);
+            b.dedup = b.dedup.or(d.dedup);
+            b.prefer = b.prefer.take().or_else(|| d.prefer.clone());
+            b.delay_s = b.delay_s.or(d.delay_s);
+            b.upgrade = b.upgrade.or(d.upgrade);
+            b.delete_replaced = b.delete_replaced.or(d.delete_replaced);
+            b.show_filter = b.show_filter.take().or_else(|| d.show_filter.clone());
+            b.min_size_mb = b.min_size_mb.or(d.min_size_mb);
+            b.max_size_mb = b.max_size_mb.or(d.max_size_mb);
+            b.extensions = b.extensions.take().or_else(|| d.extensions.clone());
+            b.not_extensions = b.not_extensions.take().or_else(|| d.not_extensions.clone());
+            b.upload_torrent = b.upload_torrent.or(d.upload_torrent);
+            b.cookie = b.cookie.take().or_else(|| d.cookie.clone());
+            b.client = b.client.take().or_else(|| d.client.clone());
+        }
+    }
+
+    /// 在 `update_default` 之后调用，拒绝不会生效的设置
+    pub fn validate(&self) -> anyhow::Result<()> {
+        use anyhow::Context;
+        for feed in self.feed.iter() {
+            feed.validate()
+                .with_context(|| format!("invalid feed {}", feed.name()))?;
+        }
+        Ok(())
+    }
+
+    /// 手动指定的 TMDB 条目
+    pub fn tmdb_override(&self, name: &str, media_type: MediaType) -> Option<i64> {
+        pick_override(&self.shows, &[name], |s| {
+            s.tmdb_id.is_some() && s.media_type == media_type
+        })
+        .and_then(|s| s.tmdb_id)
+    }
+
+    /// 修正季或集的手动修正
+    pub fn episode_override(&self, names: &[&str]) -> Option<&ShowOverride> {
+        pick_override(&self.shows, names, ShowOverride::adjusts_episode)
+    }
+}
+
+/// 满足 `f` 的手动修正里，按名字匹配的优先于按正则匹配的，同样匹配时取靠前的
+fn pick_override<'a>(
+    shows: &'a [ShowOverride],
+    names: &[&str],
+    f: impl Fn(&ShowOverride) -> bool,
+) -> Option<&'a ShowOverride> {
+    let shows = || shows.iter().filter(|s| f(s));
+    shows()
+        .find(|s| names.iter().any(|n| s.name.as_deref() == Some(*n)))
+        .or_else(|| shows().find(|s| names.iter().any(|n| s.matches(n))))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TmdbConfig {
+    /// 搜索结果的置信度低于此值时不采用，记为未匹配
+    #[serde(default = "default_min_confidence")]
+    pub min_confidence: f64,
+
+    /// 已匹配条目的缓存天数，过期后按 id 刷新名字、年份等信息
+    #[serde(default = "default_cache_ttl_days")]
+    pub cache_ttl_days: u64,
+
+    /// 未匹配的剧名多少小时后重新搜索
+    #[serde(default = "default_unmatched_retry_hours")]
+    pub unmatched_retry_hours: u64,
+}
+impl Default for TmdbConfig {
+    fn default() -> Self {
+        Self {
+            min_confidence: default_min_confidence(),
+            cache_ttl_days: default_cache_ttl_days(),
+            unmatched_retry_hours: default_unmatched_retry_hours(),
         }
     }
 }
+fn default_min_confidence() -> f64 {
+    0.6
+}
+fn default_cache_ttl_days() -> u64 {
+    30
+}
+fn default_unmatched_retry_hours() -> u64 {
+    6
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MetadataConfig {
+    /// 按顺序搜索的元数据源，剧名取第一个匹配上的源，其余的源只记录 id。
+    /// 绝对集数换算和按 TMDB id 去重都需要 TMDB 匹配上，只有其他源匹配上时
+    /// 不换算集数，去重按剧名进行
+    #[serde(default = "default_providers")]
+    pub providers: Vec<MetadataProvider>,
+}
+impl Default for MetadataConfig {
+    fn default() -> Self {
+        Self {
+            providers: default_providers(),
+        }
+    }
+}
+fn default_providers() -> Vec<MetadataProvider> {
+    vec![MetadataProvider::Tmdb]
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataProvider {
+    Tmdb,
+    Bangumi,
+    Anilist,
+}
+
+/// 单部剧的手动修正，优先于 TMDB 搜索和缓存
+#[derive(Debug, Deserialize, Clone)]
+pub struct ShowOverride {
+    /// 识别出的剧名或 TMDB 剧名
+    pub name: Option<String>,
+    /// 匹配识别出的剧名的正则，和 `name` 任选其一
+    #[serde(default, with = "serde_regex")]
+    pub pattern: Option<regex::Regex>,
+    /// 直接指定 TMDB 条目，不再搜索
+    pub tmdb_id: Option<i64>,
+    /// `tmdb_id` 的条目类型，剧场版填 `movie`
+    #[serde(default)]
+    pub media_type: MediaType,
+    /// 强制使用的季
+    pub season: Option<i64>,
+    /// 季偏移，在识别出的季上加减
+    #[serde(default)]
+    pub season_offset: i64,
+    /// 集数偏移，如第二季从 25 集开始则为 -24
+    #[serde(default)]
+    pub episode_offset: i64,
+}
+impl ShowOverride {
+    pub fn matches(&self, name: &str) -> bool {
+        self.name.as_deref() == Some(name)
+            || self.pattern.as_ref().is_some_and(|p| p.is_match(name))
+    }
+
+    /// 是否手动修正了季或集，是则不再自动换算绝对集数
+    pub fn adjusts_episode(&self) -> bool {
+        self.season.is_some() || self.season_offset != 0 || self.episode_offset != 0
+    }
+
+    pub fn apply(&self, season: i64, episode: i64) -> (i64, i64) {
+        (
+            self.season.unwrap_or(season + self.season_offset),
+            episode + self.episode_offset,
+        )
+    }
+}
 
 fn default_timeout() -> u64 {
     10
 }
 
-#[derive(Deserialize)]
+fn default_reconcile() -> u64 {
+    3600
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DownloaderConfig {
+    Qbittorrent(QbConfig),
+    Transmission(TransmissionConfig),
+    Deluge(DelugeConfig),
+    Aria2(Aria2Config),
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct QbConfig {
     pub base_url: String,
+    /// 留空表示 qb 对本机免认证
+    #[serde(default)]
     pub username: String,
+    #[serde(default)]
     pub password: String,
+    /// 设置后不再用用户名密码登录
+    pub api_key: Option<String>,
+}
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct NamedClient {
+    pub name: String,
+    #[serde(flatten)]
+    pub config: DownloaderConfig,
+}
+
+/// Transmission RPC，如 `http://127.0.0.1:9091/transmission/rpc`
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct TransmissionConfig {
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+/// Deluge Web UI 的 JSON-RPC，如 `http://127.0.0.1:8112/json`
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct DelugeConfig {
+    pub url: String,
+    pub password: String,
+}
+
+/// aria2 JSON-RPC，如 `http://127.0.0.1:6800/jsonrpc`
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct Aria2Config {
+    pub url: String,
+    /// `--rpc-secret`
+    pub secret: Option<String>,
+}
+
+impl QbConfig {
+    pub fn auth(&self) -> crate::qb::Auth {
+        use crate::qb::Auth;
+        match &self.api_key {
+            Some(key) => Auth::ApiKey(key.clone()),
+            None if self.username.is_empty() => Auth::Bypass,
+            None => Auth::Password {
+                username: self.username.clone(),
+                password: self.password.clone(),
+            },
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
@@ -96,6 +387,26 @@ impl Feed {
             Self::Rss(rss) => &mut rss.base,
         }
     }
+    /// 只有磁力链接时拿不到种子文件
+    pub fn magnet_only(&self) -> bool {
+        match self {
+            Self::Rss(rss) => rss.site.magnet_only(),
+        }
+    }
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.magnet_only() && self.base().checks_torrent() {
+            anyhow::bail!(
+                "the site only has magnet links, min_size_mb, max_size_mb, extensions and not_extensions can not be checked"
+            );
+        }
+        let base = self.base();
+        if self.magnet_only() && (base.upload_torrent() || base.cookie.is_some()) {
+            anyhow::bail!(
+                "the site only has magnet links, upload_torrent and cookie have no effect"
+            );
+        }
+        Ok(())
+    }
 }
 
 #[derive(Deserialize, Clone)]
@@ -115,6 +426,14 @@ pub enum RssSite {
     #[serde(alias = "动漫花园")]
     Dmhy,
 }
+impl RssSite {
+    /// RSS 里的链接都是磁力链接
+    pub fn magnet_only(&self) -> bool {
+        match self {
+            Self::Comicat | Self::Dmhy => true,
+        }
+    }
+}
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct FeedBase {
@@ -136,6 +455,24 @@ pub struct FeedBase {
 
     pub ratio_limit: Option<f64>,
 
+    /// 添加后暂停，qb 5.0 起叫 stopped
+    #[serde(alias = "stopped")]
+    pub paused: Option<bool>,
+    /// 跳过哈希校验
+    pub skip_checking: Option<bool>,
+    /// 按顺序下载
+    pub sequential_download: Option<bool>,
+    /// 先下载首尾文件块
+    pub first_last_piece_prio: Option<bool>,
+    /// 上传限速，KiB/s
+    pub up_limit_kb: Option<u64>,
+    /// 下载限速，KiB/s
+    pub dl_limit_kb: Option<u64>,
+    /// 做种时间上限，分钟，-1 为不限制
+    pub seeding_time_limit_min: Option<i64>,
+    /// 未完成的种子的下载目录，完成后移到 `savepath`
+    pub download_path: Option<String>,
+
     /// filter，要包含的正则
     #[serde(default, with = "serde_regex")]
     pub filters: Option<Vec<regex::Regex>>,
@@ -143,11 +480,136 @@ pub struct FeedBase {
     /// not_filters，排除的正则
     #[serde(default, with = "serde_regex")]
     pub not_filters: Option<Vec<regex::Regex>>,
+
+    /// 是否为动画，TMDB 匹配时优先动画类型
+    pub anime: Option<bool>,
+
+    /// 元数据的语言，默认 `zh-CN`
+    pub language: Option<String>,
+    /// 没有 `language` 的译名时使用的语言
+    pub fallback_language: Option<String>,
+    /// 地区，如 `US`，TMDB 只对电影生效
+    pub region: Option<String>,
+    /// 是否搜索成人内容，默认是
+    pub include_adult: Option<bool>,
+    /// 命名使用译名还是原名，默认译名
+    pub naming: Option<Naming>,
+
+    /// 同一集（TMDB id + 季 + 集，没有 TMDB id 时为剧名 + 季 + 集）在不同字幕组、
+    /// 不同 feed 之间的去重，默认不去重
+    pub dedup: Option<Dedup>,
+
+    /// 同一集有多个版本时的偏好
+    pub prefer: Option<Preference>,
+
+    /// 等待期，同一集第一次出现后等待这么久，只下载其中得分最高的版本
+    pub delay_s: Option<u64>,
+
+    /// 已下载的集出现更好的版本时是否替换，默认不替换
+    pub upgrade: Option<Upgrade>,
+    /// 替换后是否从 qb 删除旧种子和文件
+    pub delete_replaced: Option<bool>,
+
+    /// 按识别出的剧集信息过滤
+    pub show_filter: Option<ShowFilter>,
+
+    /// 种子总大小的下限，设置了种子规则时会先下载种子文件。
+    /// 动漫花园和动漫猫只有磁力链接，拿不到种子文件，设置了种子规则时拒绝启动
+    pub min_size_mb: Option<u64>,
+    /// 种子总大小的上限
+    pub max_size_mb: Option<u64>,
+    /// 种子中最大的文件必须是这些扩展名之一，如 `["mkv", "mp4"]`
+    pub extensions: Option<Vec<String>>,
+    /// 种子中不能有这些扩展名的文件，如 `["exe"]`
+    pub not_extensions: Option<Vec<String>>,
+
+    /// 由 rss-qb 通过自己的代理下载种子文件再上传给 qb，而不是让 qb 自己下载。
+    /// 和 `cookie` 一样，对只有磁力链接的动漫花园和动漫猫设置时拒绝启动
+    pub upload_torrent: Option<bool>,
+    /// 下载种子文件时带上的 cookie
+    pub cookie: Option<String>,
+
+    /// 使用的下载器，对应 `[[client]]` 的 `name`
+    pub client: Option<String>,
 }
 impl FeedBase {
     pub fn interval_s(&self) -> u64 {
         self.interval_s.unwrap_or_else(default_interval)
     }
+    pub fn anime(&self) -> bool {
+        self.anime.unwrap_or(false)
+    }
+    pub fn locale(&self) -> Locale {
+        let d = Locale::default();
+        Locale {
+            language: self.language.clone().unwrap_or(d.language),
+            fallback_language: self.fallback_language.clone(),
+            region: self.region.clone(),
+            include_adult: self.include_adult.unwrap_or(d.include_adult),
+        }
+    }
+    pub fn naming(&self) -> Naming {
+        self.naming.unwrap_or_default()
+    }
+    pub fn dedup(&self) -> Dedup {
+        self.dedup.unwrap_or_default()
+    }
+    pub fn upgrade(&self) -> Upgrade {
+        self.upgrade.unwrap_or_default()
+    }
+    pub fn delete_replaced(&self) -> bool {
+        self.delete_replaced.unwrap_or(false)
+    }
+    /// 版本的得分，越大越好
+    pub fn score(&self, info: &crate::gpt::ShowInfo, title: &str) -> i64 {
+        match &self.prefer {
+            Some(prefer) => prefer.score(info, title),
+            None => Preference::default().score(info, title),
+        }
+    }
+    /// 识别后的过滤
+    pub fn accepts(&self, info: &crate::gpt::ShowInfo) -> bool {
+        self.show_filter.as_ref().is_none_or(|f| f.accepts(info))
+    }
+    pub fn upload_torrent(&self) -> bool {
+        self.upload_torrent.unwrap_or(false)
+    }
+    /// 是否需要下载种子文件检查大小和文件
+    pub fn checks_torrent(&self) -> bool {
+        self.min_size_mb.is_some()
+            || self.max_size_mb.is_some()
+            || self.extensions.is_some()
+            || self.not_extensions.is_some()
+    }
+    pub fn check_torrent(&self, meta: &crate::torrent::Metainfo) -> bool {
+        const MB: u64 = 1024 * 1024;
+        let size = meta.total_size();
+        if self.min_size_mb.is_some_and(|min| size < min * MB) {
+            debug!("torrent {} is too small: {size}", meta.name);
+            return false;
+        }
+        if self.max_size_mb.is_some_and(|max| size > max * MB) {
+            debug!("torrent {} is too large: {size}", meta.name);
+            return false;
+        }
+        let has = |exts: &[String], file: &crate::torrent::File| {
+            file.ext()
+                .is_some_and(|e| exts.iter().any(|x| x.eq_ignore_ascii_case(&e)))
+        };
+        if let Some(exts) = &self.extensions {
+            if !meta.largest().is_some_and(|f| has(exts, f)) {
+                debug!("torrent {} has no allowed main file", meta.name);
+                return false;
+            }
+        }
+        if let Some(exts) = &self.not_extensions {
+            if let Some(f) = meta.files.iter().find(|f| has(exts, f)) {
+                debug!("torrent {} contains {}", meta.name, f.path);
+                return false;
+            }
+        }
+        true
+    }
     pub fn filter(&self, item: &crate::db::Item) -> bool {
         for filter in self.filters.as_deref().unwrap_or_default() {
             if !filter.is_match(&item.title) {
@@ -165,6 +627,201 @@ impl FeedBase {
     }
 }
 
+/// 元数据搜索的语言和地区
+#[derive(Debug, Clone)]
+pub struct Locale {
+    pub language: String,
+    pub fallback_language: Option<String>,
+    pub region: Option<String>,
+    pub include_adult: bool,
+}
+impl Default for Locale {
+    fn default() -> Self {
+        Self {
+            language: "zh-CN".to_string(),
+            fallback_language: None,
+            region: None,
+            include_adult: true,
+        }
+    }
+}
+impl Locale {
+    /// 条目的原始语言是否就是要求的语言，此时原名即译名
+    pub fn is_original(&self, original_language: &str) -> bool {
+        self.language.split('-').next() == Some(original_language)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Naming {
+    /// 按 `language` 的译名
+    #[default]
+    Localized,
+    /// 原名
+    Original,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Dedup {
+    /// 都下载
+    #[default]
+    Allow,
+    /// 只下载第一个
+    First,
+    /// 只在比已下载的版本更好时下载
+    Best,
+}
+
+/// 版本偏好，每项越靠前越好，不在列表中的排在最后。
+/// 依次比较字幕组、分辨率、语言、编码，均为不区分大小写的包含匹配
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct Preference {
+    /// 匹配识别出的字幕组
+    #[serde(default)]
+    pub fansubs: Vec<String>,
+    /// 匹配识别出的分辨率，不填时分辨率越高越好
+    #[serde(default)]
+    pub resolutions: Vec<String>,
+    /// 匹配识别出的语言
+    #[serde(default)]
+    pub languages: Vec<String>,
+    /// 匹配种子标题，如 `HEVC`、`x265`
+    #[serde(default)]
+    pub codecs: Vec<String>,
+}
+impl Preference {
+    pub fn score(&self, info: &crate::gpt::ShowInfo, title: &str) -> i64 {
+        fn rank(prefs: &[String], value: &str) -> i64 {
+            let value = value.to_lowercase();
+            prefs
+                .iter()
+                .position(|p| value.contains(&p.to_lowercase()))
+                .map(|i| (prefs.len() - i) as i64)
+                .unwrap_or(0)
+        }
+        let resolution = info.resolution.as_deref().unwrap_or_default();
+        let resolution = if self.resolutions.is_empty() {
+            crate::parser::resolution_rank(resolution)
+        } else {
+            rank(&self.resolutions, resolution)
+        };
+        // 每项占 4 位十进制
+        [
+            rank(&self.fansubs, &info.fansub),
+            resolution,
+            rank(&self.languages, &info.language),
+            rank(&self.codecs, title),
+        ]
+        .into_iter()
+        .fold(0, |acc, r| acc * 10_000 + r.min(9_999))
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Upgrade {
+    /// 不替换
+    #[default]
+    Never,
+    /// 同一字幕组的修正版，如 `v2`
+    Version,
+    /// 修正版，或得分更高的版本
+    Better,
+}
+
+/// 识别后的过滤条件，不填的项不限制
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ShowFilter {
+    /// 允许的字幕组，不区分大小写的包含匹配
+    #[serde(default)]
+    pub fansubs: Vec<String>,
+    /// 最低分辨率，如 `1080p`，识别不出分辨率的不过滤
+    pub min_resolution: Option<String>,
+    /// 允许的语言，不区分大小写的包含匹配
+    #[serde(default)]
+    pub languages: Vec<String>,
+    /// 从这一集开始，如 `S02E05`，或整季 `S02`
+    pub from: Option<EpisodeRef>,
+    /// 到这一集为止
+    pub to: Option<EpisodeRef>,
+}
+impl ShowFilter {
+    pub fn accepts(&self, info: &crate::gpt::ShowInfo) -> bool {
+        fn any(allowed: &[String], value: &str) -> bool {
+            let value = value.to_lowercase();
+            allowed.is_empty() || allowed.iter().any(|a| value.contains(&a.to_lowercase()))
+        }
+        if !any(&self.fansubs, &info.fansub) {
+            debug!("{} filtered out by fansub {}", info.show, info.fansub);
+            return false;
+        }
+        if !any(&self.languages, &info.language) {
+            debug!("{} filtered out by language {}", info.show, info.language);
+            return false;
+        }
+        // 标题里没有分辨率时不过滤
+        if let (Some(min), Some(resolution)) = (&self.min_resolution, &info.resolution) {
+            let rank = crate::parser::resolution_rank;
+            if rank(resolution) < rank(min) {
+                debug!("{} filtered out by resolution {resolution}", info.show);
+                return false;
+            }
+        }
+        // 剧场版没有季和集，合集只要有一集在范围内即可
+        if info.kind == crate::gpt::ShowKind::Movie {
+            return true;
+        }
+        let start = (info.season, info.episode);
+        let end = (info.season, info.episode_end.unwrap_or(info.episode));
+        if self.from.is_some_and(|from| end < from.first()) {
+            debug!(
+                "{} S{}E{} is before range",
+                info.show, info.season, info.episode
+            );
+            return false;
+        }
+        if self.to.is_some_and(|to| start > to.last()) {
+            debug!(
+                "{} S{}E{} is after range",
+                info.show, info.season, info.episode
+            );
+            return false;
+        }
+        true
+    }
+}
+
+/// `S02E05` 或 `S02`
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct EpisodeRef {
+    pub season: i64,
+    pub episode: Option<i64>,
+}
+impl EpisodeRef {
+    fn first(&self) -> (i64, i64) {
+        (self.season, self.episode.unwrap_or(0))
+    }
+    fn last(&self) -> (i64, i64) {
+        (self.season, self.episode.unwrap_or(i64::MAX))
+    }
+}
+impl TryFrom<String> for EpisodeRef {
+    type Error = anyhow::Error;
+    fn try_from(s: String) -> anyhow::Result<Self> {
+        let re = regex::Regex::new(r"^(?i)S(\d+)(?:E(\d+))?$")?;
+        let c = re
+            .captures(s.trim())
+            .ok_or_else(|| anyhow::anyhow!("invalid episode {s}, expect S02E05 or S02"))?;
+        Ok(Self {
+            season: c[1].parse()?,
+            episode: c.get(2).map(|e| e.as_str().parse()).transpose()?,
+        })
+    }
+}
+
 fn default_interval() -> u64 {
     15 * 60
 }
@@ -199,6 +856,16 @@ pub struct GptConfig {
     pub retry: u8,
     pub better_model: String,
     pub better_since: u8,
+
+    /// 各模型价格，key 为模型名
+    #[serde(default)]
+    pub prices: HashMap<String, ModelPrice>,
+
+    /// 每月预算，超出后按 `over_budget` 处理
+    pub monthly_budget: Option<f64>,
+
+    #[serde(default)]
+    pub over_budget: OverBudget,
 }
 impl GptConfig {
     pub fn model(&self, time: u8) -> &str {
@@ -208,6 +875,35 @@ impl GptConfig {
             &self.model
         }
     }
+
+    /// 估算一次请求的花费，没有配置价格的模型记为 0
+    pub fn cost(&self, model: &str, prompt_tokens: u32, completion_tokens: u32) -> f64 {
+        match self.prices.get(model) {
+            Some(price) => {
+                (prompt_tokens as f64 * price.prompt + completion_tokens as f64 * price.completion)
+                    / 1_000_000.0
+            }
+            None => 0.0,
+        }
+    }
+}
+
+/// 每百万 token 的价格
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct ModelPrice {
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+/// 超出每月预算后的行为
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OverBudget {
+    /// 用本地规则解析标题
+    #[default]
+    Local,
+    /// 暂停识别，等下个月
+    Pause,
 }
 
 #[cfg(test)]
@@ -228,6 +924,181 @@ mod tests {
         assert!(h.h.is_none());
     }
 
+    #[test]
+    fn show_override() {
+        #[derive(Deserialize)]
+        struct H {
+            show: Vec<ShowOverride>,
+        }
+        let h: H = toml::from_str(
+            r#"
+            [[show]]
+            name = "某剧"
+            tmdb_id = 123
+
+            [[show]]
+            pattern = "^另一部剧"
+            tmdb_id = 456
+            media_type = "movie"
+            season_offset = 1
+            episode_offset = -12
+            "#,
+        )
+        .unwrap();
+        assert!(h.show[0].matches("某剧"));
+        assert!(!h.show[0].adjusts_episode());
+        assert_eq!(h.show[0].media_type, MediaType::Tv);
+        assert_eq!(h.show[1].media_type, MediaType::Movie);
+        assert!(h.show[1].matches("另一部剧 第二季"));
+        assert_eq!(h.show[1].apply(1, 13), (2, 1));
+
+        // 按名字匹配的优先，即使在后面
+        let h: H = toml::from_str(
+            r#"
+            [[show]]
+            pattern = "^某剧"
+            tmdb_id = 123
+
+            [[show]]
+            name = "某剧 第二季"
+            season = 2
+            "#,
+        )
+        .unwrap();
+        let episode = |names: &[&str]| pick_override(&h.show, names, ShowOverride::adjusts_episode);
+        assert_eq!(episode(&["某剧 第二季"]).and_then(|s| s.season), Some(2));
+        assert!(episode(&["某剧"]).is_none());
+        let tmdb = |name| pick_override(&h.show, &[name], |s| s.tmdb_id.is_some());
+        assert_eq!(tmdb("某剧 第二季").and_then(|s| s.tmdb_id), Some(123));
+    }
+
+    #[test]
+    fn magnet_only_feed() {
+        let feed = |extra: &str| {
+            let s =
+                format!("type = \"rss\"\nname = \"a\"\nsite = \"dmhy\"\nsearch = \"a\"\n{extra}");
+            toml::from_str::<Feed>(&s).unwrap()
+        };
+        assert!(feed("").validate().is_ok());
+        assert!(feed("min_size_mb = 50").validate().is_err());
+        assert!(feed("extensions = [\"mkv\"]").validate().is_err());
+        assert!(feed("upload_torrent = false").validate().is_ok());
+        assert!(feed("upload_torrent = true").validate().is_err());
+        assert!(feed("cookie = \"uid=1\"").validate().is_err());
+    }
+
+    #[test]
+    fn preference() {
+        let prefer: Preference = toml::from_str(
+            r#"
+            fansubs = ["LoliHouse", "ANi"]
+            resolutions = ["1080p", "720p"]
+            languages = ["简日", "繁中"]
+            codecs = ["HEVC", "AVC"]
+            "#,
+        )
+        .unwrap();
+        let info = |fansub: &str, resolution: &str, language: &str| crate::gpt::ShowInfo {
+            kind: Default::default(),
+            fansub: fansub.to_string(),
+            show: "某剧".to_string(),
+            season: 1,
+            episode: 1,
+            episode_end: None,
+            resolution: Some(resolution.to_string()),
+            language: language.to_string(),
+            year: 0,
+            tmdb_id: 0,
+        };
+        let score = |fansub, resolution, language, title| {
+            prefer.score(&info(fansub, resolution, language), title)
+        };
+        assert!(score("LoliHouse", "720p", "繁中", "") > score("ANi", "1080p", "简日", ""));
+        assert!(score("ANi", "1080p", "繁中", "") > score("ANi", "720p", "简日", ""));
+        assert!(score("ANi", "1080p", "简日", "") > score("ANi", "1080p", "繁中", ""));
+        assert!(score("ANi", "1080p", "简日", "[HEVC]") > score("ANi", "1080p", "简日", "[AVC]"));
+        assert!(score("ANi", "1080p", "简日", "[AVC]") > score("Other", "1080p", "简日", "[AVC]"));
+
+        let default = Preference::default();
+        assert!(
+            default.score(&info("ANi", "1080p", "简日"), "")
+                > default.score(&info("LoliHouse", "720p", "简日"), "")
+        );
+    }
+
+    #[test]
+    fn show_filter() {
+        let filter: ShowFilter = toml::from_str(
+            r#"
+            fansubs = ["LoliHouse"]
+            min_resolution = "1080p"
+            languages = ["简"]
+            from = "S02E05"
+            to = "S03"
+            "#,
+        )
+        .unwrap();
+        let info = |season, episode, resolution: &str| crate::gpt::ShowInfo {
+            kind: Default::default(),
+            fansub: "LoliHouse".to_string(),
+            show: "某剧".to_string(),
+            season,
+            episode,
+            episode_end: None,
+            resolution: Some(resolution.to_string()),
+            language: "简日".to_string(),
+            year: 0,
+            tmdb_id: 0,
+        };
+        assert!(filter.accepts(&info(2, 5, "1080p")));
+        assert!(filter.accepts(&info(3, 24, "2160p")));
+        assert!(!filter.accepts(&info(2, 4, "1080p")));
+        assert!(!filter.accepts(&info(4, 1, "1080p")));
+        assert!(!filter.accepts(&info(2, 6, "720p")));
+        assert!(!filter.accepts(&crate::gpt::ShowInfo {
+            fansub: "ANi".to_string(),
+            ..info(2, 6, "1080p")
+        }));
+        assert!(toml::from_str::<ShowFilter>(r#"from = "E05""#).is_err());
+    }
+
+    #[test]
+    fn parse_named_clients() {
+        #[derive(Deserialize)]
+        struct C {
+            client: Vec<NamedClient>,
+        }
+        let c: C = toml::from_str(
+            r#"
+            [[client]]
+            name = "anime"
+            kind = "qbittorrent"
+            base_url = "http://127.0.0.1:8080"
+            username = "admin"
+            password = "adminadmin"
+
+            [[client]]
+            name = "tv"
+            kind = "transmission"
+            url = "http://192.168.1.10:9091/transmission/rpc"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(c.client[0].name, "anime");
+        assert!(matches!(
+            &c.client[0].config,
+            DownloaderConfig::Qbittorrent(QbConfig { username, .. }) if username == "admin"
+        ));
+        assert_eq!(
+            c.client[1].config,
+            DownloaderConfig::Transmission(TransmissionConfig {
+                url: "http://192.168.1.10:9091/transmission/rpc".to_string(),
+                username: None,
+                password: None,
+            })
+        );
+    }
+
     // #[test]
     // fn parse_templates_config() {
     //     let s = std::fs::read_to_string("./templates/config.toml").unwrap();
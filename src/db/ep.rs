@@ -1,10 +1,19 @@
 use super::*;
 
+/// 已下载的一集，`tmdb_id` 为 0 时按剧名去重
 pub struct SeriesEpisode {
     pub series_name: String,
-    pub series_season: String,
-    pub series_episode: String,
+    pub tmdb_id: i64,
+    pub series_season: i64,
+    pub series_episode: i64,
     pub item_guid: String,
+    pub feed: String,
+    /// 越大越好，用于保留最好的版本
+    pub quality: i64,
+    pub torrent_id: i64,
+    pub fansub: String,
+    /// 字幕组的修正版本，如 `v2`
+    pub version: i64,
 }
 
 pub struct Transaction {
@@ -13,36 +22,72 @@ pub struct Transaction {
 }
 
 impl SeriesEpisode {
-    pub async fn exists(&self, tx: &Transaction) -> Result<bool> {
-        let stmt = sqlx::query_scalar!(
-            r#"
-                SELECT COUNT(*)
+    /// 同一集已下载的版本，按下载顺序
+    pub async fn downloaded(&self, pool: &Pool) -> Result<Vec<Self>> {
+        let ans = if self.tmdb_id != 0 {
+            sqlx::query_as!(
+                Self,
+                r#"
+                SELECT
+                    `series_name`, `tmdb_id`, `series_season`, `series_episode`, `item_guid`,
+                    `feed`, `quality`, `torrent_id`, `fansub`, `version`
                 FROM `series`
                 WHERE
-                    `series_name` = ?
-                    AND series_season = ? 
-                    AND series_episode = ?
+                    `tmdb_id` = ?
+                    AND `series_season` = ?
+                    AND `series_episode` = ?
+                ORDER BY `id`
                 ;"#,
-            self.series_name,
-            self.series_season,
-            self.series_episode
-        );
-        let count: i32 = stmt.fetch_one(&tx.pool).await?;
-        Ok(count > 0)
+                self.tmdb_id,
+                self.series_season,
+                self.series_episode
+            )
+            .fetch_all(pool)
+            .await?
+        } else {
+            sqlx::query_as!(
+                Self,
+                r#"
+                SELECT
+                    `series_name`, `tmdb_id`, `series_season`, `series_episode`, `item_guid`,
+                    `feed`, `quality`, `torrent_id`, `fansub`, `version`
+                FROM `series`
+                WHERE
+                    `tmdb_id` = 0
+                    AND `series_name` = ?
+                    AND `series_season` = ?
+                    AND `series_episode` = ?
+                ORDER BY `id`
+                ;"#,
+                self.series_name,
+                self.series_season,
+                self.series_episode
+            )
+            .fetch_all(pool)
+            .await?
+        };
+        Ok(ans)
     }
 
     pub async fn insert(self, tx: &mut Transaction) -> Result<()> {
         sqlx::query!(
             r#"
             INSERT INTO `series`
-                (`series_name`, `series_season`, `series_episode`, `item_guid`)
+                (`series_name`, `tmdb_id`, `series_season`, `series_episode`, `item_guid`, `feed`, `quality`,
+                `torrent_id`, `fansub`, `version`)
             VALUES
-                (?, ?, ?, ?);
+                (?, ?, ?, ?, ?, ?, ?, ?, ?, ?);
             "#,
             self.series_name,
+            self.tmdb_id,
             self.series_season,
             self.series_episode,
-            self.item_guid
+            self.item_guid,
+            self.feed,
+            self.quality,
+            self.torrent_id,
+            self.fansub,
+            self.version
         )
         .execute(&tx.pool)
         .await?;
@@ -0,0 +1,87 @@
+use super::*;
+
+/// 一次 GPT 请求的用量记录
+#[derive(Debug)]
+pub struct GptUsage {
+    pub feed: String,
+    pub model: String,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub latency_ms: i64,
+    /// `ok` / `invalid` / `error`
+    pub outcome: String,
+    pub cost: f64,
+    pub created_at: i64,
+}
+
+/// 按 feed 和月份汇总的用量
+#[derive(Debug, serde::Serialize)]
+pub struct GptUsageTotal {
+    pub feed: String,
+    /// `YYYY-MM`
+    pub month: String,
+    pub requests: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub cost: f64,
+}
+
+impl GptUsage {
+    pub async fn insert(&self, pool: &Pool) -> Result<()> {
+        sqlx::query!(
+            r"INSERT INTO `gpt_usage`
+                (`feed`, `model`, `prompt_tokens`, `completion_tokens`, `latency_ms`, `outcome`, `cost`, `created_at`)
+            VALUES
+                (?, ?, ?, ?, ?, ?, ?, ?);",
+            self.feed,
+            self.model,
+            self.prompt_tokens,
+            self.completion_tokens,
+            self.latency_ms,
+            self.outcome,
+            self.cost,
+            self.created_at,
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// `since` 之后（unix 秒）所有 feed 的总花费
+    pub async fn cost_since(since: i64, pool: &Pool) -> Result<f64> {
+        let cost = sqlx::query_scalar!(
+            r#"SELECT
+                COALESCE(SUM(`cost`), 0.0) AS "cost!: f64"
+            FROM
+                `gpt_usage`
+            WHERE
+                `created_at` >= ?;"#,
+            since
+        )
+        .fetch_one(pool)
+        .await?;
+        Ok(cost)
+    }
+
+    pub async fn totals(pool: &Pool) -> Result<Vec<GptUsageTotal>> {
+        let ans = sqlx::query_as!(
+            GptUsageTotal,
+            r#"SELECT
+                `feed`,
+                strftime('%Y-%m', `created_at`, 'unixepoch') AS "month!: String",
+                COUNT(*) AS "requests!: i64",
+                SUM(`prompt_tokens`) AS "prompt_tokens!: i64",
+                SUM(`completion_tokens`) AS "completion_tokens!: i64",
+                SUM(`cost`) AS "cost!: f64"
+            FROM
+                `gpt_usage`
+            GROUP BY
+                `feed`, 2
+            ORDER BY
+                2 DESC, `feed`;"#
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(ans)
+    }
+}
@@ -6,6 +6,8 @@ pub struct Item {
     pub title: String,
     pub link: String,
     pub enclosure: String,
+    /// 发布时间，不入库
+    pub pub_date: Option<i64>,
 }
 
 impl Item {
@@ -5,11 +5,17 @@ pub type Pool = sqlx::SqlitePool;
 mod item;
 pub use item::Item;
 
-// mod ep;
-// pub use ep::{SeriesEpisode, Transaction as EpTransaction};
+mod ep;
+pub use ep::{SeriesEpisode, Transaction as EpTransaction};
 
 mod tmdb;
-pub use tmdb::TmdbShow;
+pub use tmdb::{TmdbSeason, TmdbShow, TmdbUnmatched};
 
 mod torrent_info;
-pub use torrent_info::TorrentInfo;
+pub use torrent_info::{TorrentInfo, TorrentState};
+
+mod pending;
+pub use pending::Pending;
+
+mod gpt_usage;
+pub use gpt_usage::{GptUsage, GptUsageTotal};
@@ -0,0 +1,120 @@
+use super::*;
+
+/// 等待期内的候选版本。`tmdb_id`、`show`、`kind`、`season`、`episode` 相同的为同一集
+#[derive(Debug)]
+pub struct Pending {
+    pub id: i64,
+    pub feed: String,
+    pub guid: String,
+    pub title: String,
+    pub link: String,
+    pub enclosure: String,
+    pub show: String,
+    pub year: i64,
+    pub tmdb_id: i64,
+    pub kind: String,
+    pub season: i64,
+    pub episode: i64,
+    pub episode_end: Option<i64>,
+    pub fansub: String,
+    pub resolution: String,
+    pub language: String,
+    pub score: i64,
+    pub created_at: i64,
+}
+
+impl Pending {
+    pub async fn insert(&self, pool: &Pool) -> Result<()> {
+        sqlx::query!(
+            r"INSERT INTO `pending`
+                (`feed`, `guid`, `title`, `link`, `enclosure`, `show`, `year`, `tmdb_id`, `kind`,
+                `season`, `episode`, `episode_end`, `fansub`, `resolution`, `language`, `score`, `created_at`)
+            VALUES
+                (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
+            self.feed,
+            self.guid,
+            self.title,
+            self.link,
+            self.enclosure,
+            self.show,
+            self.year,
+            self.tmdb_id,
+            self.kind,
+            self.season,
+            self.episode,
+            self.episode_end,
+            self.fansub,
+            self.resolution,
+            self.language,
+            self.score,
+            self.created_at,
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// 第一次出现早于 `before` 的集的所有候选，按得分从高到低
+    pub async fn due(feed: &str, before: i64, pool: &Pool) -> Result<Vec<Self>> {
+        let ans = sqlx::query_as!(
+            Self,
+            r"SELECT
+                p.`id`, p.`feed`, p.`guid`, p.`title`, p.`link`, p.`enclosure`, p.`show`, p.`year`,
+                p.`tmdb_id`, p.`kind`, p.`season`, p.`episode`, p.`episode_end`, p.`fansub`,
+                p.`resolution`, p.`language`, p.`score`, p.`created_at`
+            FROM
+                `pending` p
+            WHERE
+                p.`feed` = ?
+                AND EXISTS (
+                    SELECT 1 FROM `pending` q
+                    WHERE
+                        q.`feed` = p.`feed` AND q.`tmdb_id` = p.`tmdb_id` AND q.`show` = p.`show`
+                        AND q.`kind` = p.`kind` AND q.`season` = p.`season` AND q.`episode` = p.`episode`
+                        AND q.`created_at` <= ?
+                )
+            ORDER BY
+                p.`score` DESC, p.`created_at` ASC, p.`id` ASC;",
+            feed,
+            before
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(ans)
+    }
+
+    /// 删除同一集的所有候选
+    pub async fn remove_group(&self, pool: &Pool) -> Result<u64> {
+        let r = sqlx::query!(
+            r"DELETE FROM `pending`
+            WHERE
+                `feed` = ? AND `tmdb_id` = ? AND `show` = ? AND `kind` = ?
+                AND `season` = ? AND `episode` = ?;",
+            self.feed,
+            self.tmdb_id,
+            self.show,
+            self.kind,
+            self.season,
+            self.episode
+        )
+        .execute(pool)
+        .await?;
+        Ok(r.rows_affected())
+    }
+
+    pub fn same_episode(&self, other: &Self) -> bool {
+        (
+            self.tmdb_id,
+            &self.show,
+            &self.kind,
+            self.season,
+            self.episode,
+        ) == (
+            other.tmdb_id,
+            &other.show,
+            &other.kind,
+            other.season,
+            other.episode,
+        )
+    }
+}
@@ -1,43 +1,245 @@
 use super::*;
 
+/// 元数据缓存。名字等信息来自第一个匹配上的元数据源，各源的 id 为 0 表示没有匹配；
+/// 都没有匹配时 `candidate_id` 为置信度太低的 TMDB 最佳候选
 #[derive(Debug)]
 pub struct TmdbShow {
     pub tmdb_id: i64,
     pub tmdb_name: String,
     pub year: i64,
+    pub updated_at: i64,
+    pub confidence: f64,
+    pub candidate_id: i64,
+    pub original_name: String,
+    pub poster_path: Option<String>,
+    pub bangumi_id: i64,
+    pub anilist_id: i64,
+}
+
+/// 未匹配的剧名和最佳候选
+#[derive(Debug, serde::Serialize)]
+pub struct TmdbUnmatched {
+    pub name: String,
+    pub media_type: String,
+    pub candidate_id: i64,
+    pub confidence: f64,
 }
 
 impl TmdbShow {
-    pub async fn from_name(name: &str, pool: &Pool) -> Result<Option<Self>> {
+    pub fn unmatched(name: &str) -> Self {
+        Self {
+            tmdb_id: 0,
+            tmdb_name: name.to_string(),
+            year: 0,
+            updated_at: chrono::Utc::now().timestamp(),
+            confidence: 0.0,
+            candidate_id: 0,
+            original_name: name.to_string(),
+            poster_path: None,
+            bangumi_id: 0,
+            anilist_id: 0,
+        }
+    }
+
+    pub fn is_matched(&self) -> bool {
+        self.tmdb_id != 0 || self.bangumi_id != 0 || self.anilist_id != 0
+    }
+
+    /// 海报图片地址，TMDB 只保存了路径
+    pub fn poster_url(&self) -> Option<String> {
+        self.poster_path.as_ref().map(|p| {
+            if p.starts_with("http") {
+                p.clone()
+            } else {
+                format!("https://image.tmdb.org/t/p/w500{p}")
+            }
+        })
+    }
+
+    /// `media_type` 为 `tv` 或 `movie`
+    pub async fn from_name(
+        name: &str,
+        media_type: &str,
+        language: &str,
+        pool: &Pool,
+    ) -> Result<Option<Self>> {
         let ans = sqlx::query_as!(
             Self,
             r"SELECT
-                `tmdb_name`, `year`, `tmdb_id`
+                `tmdb_name`, `year`, `tmdb_id`, `updated_at`, `confidence`, `candidate_id`,
+                `original_name`, `poster_path`, `bangumi_id`, `anilist_id`
             FROM
                 `tmdb_info`
             WHERE
-                `name` = ?
+                `name` = ? AND `media_type` = ? AND `language` = ?
             LIMIT 1;",
-            name
+            name,
+            media_type,
+            language
         )
         .fetch_optional(pool)
         .await?;
 
         Ok(ans)
     }
-    pub async fn insert_with(&self, name: &str, pool: &Pool) -> Result<()> {
+    /// 任意一条映射到该 TMDB 条目的缓存
+    pub async fn from_id(tmdb_id: i64, pool: &Pool) -> Result<Option<Self>> {
+        let ans = sqlx::query_as!(
+            Self,
+            r"SELECT
+                `tmdb_name`, `year`, `tmdb_id`, `updated_at`, `confidence`, `candidate_id`,
+                `original_name`, `poster_path`, `bangumi_id`, `anilist_id`
+            FROM
+                `tmdb_info`
+            WHERE
+                `tmdb_id` = ?
+            ORDER BY
+                `updated_at` DESC
+            LIMIT 1;",
+            tmdb_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(ans)
+    }
+
+    /// 覆盖同名的旧记录
+    pub async fn save_with(
+        &self,
+        name: &str,
+        media_type: &str,
+        language: &str,
+        pool: &Pool,
+    ) -> Result<()> {
+        let mut tx = pool.begin().await?;
+        sqlx::query!(
+            "DELETE FROM `tmdb_info` WHERE `name` = ? AND `media_type` = ? AND `language` = ?;",
+            name,
+            media_type,
+            language
+        )
+        .execute(&mut *tx)
+        .await?;
         sqlx::query!(
             r"INSERT INTO `tmdb_info`
-                (`name`, `tmdb_name`, `year`, `tmdb_id`)
+                (`name`, `media_type`, `tmdb_name`, `year`, `tmdb_id`, `updated_at`, `confidence`, `candidate_id`,
+                `original_name`, `poster_path`, `bangumi_id`, `anilist_id`, `language`)
             VALUES
-                (?, ?, ?, ?);",
+                (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
             name,
+            media_type,
             self.tmdb_name,
             self.year,
             self.tmdb_id,
+            self.updated_at,
+            self.confidence,
+            self.candidate_id,
+            self.original_name,
+            self.poster_path,
+            self.bangumi_id,
+            self.anilist_id,
+            language,
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// 删除某个剧名的缓存，下次重新搜索
+    pub async fn invalidate_name(name: &str, pool: &Pool) -> Result<u64> {
+        let r = sqlx::query!("DELETE FROM `tmdb_info` WHERE `name` = ?;", name)
+            .execute(pool)
+            .await?;
+        Ok(r.rows_affected())
+    }
+
+    /// 删除映射到某个 TMDB 条目的所有缓存，包括季列表
+    pub async fn invalidate_id(tmdb_id: i64, pool: &Pool) -> Result<u64> {
+        let mut tx = pool.begin().await?;
+        let r = sqlx::query!(
+            "DELETE FROM `tmdb_info` WHERE `tmdb_id` = ? OR `candidate_id` = ?;",
+            tmdb_id,
+            tmdb_id
         )
-        .execute(pool)
+        .execute(&mut *tx)
         .await?;
+        sqlx::query!("DELETE FROM `tmdb_season` WHERE `tmdb_id` = ?;", tmdb_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(r.rows_affected())
+    }
+
+    /// 所有 TMDB 上没有找到或置信度太低的剧名
+    pub async fn list_unmatched(pool: &Pool) -> Result<Vec<TmdbUnmatched>> {
+        let names = sqlx::query_as!(
+            TmdbUnmatched,
+            r"SELECT
+                `name`, `media_type`, `candidate_id`, `confidence`
+            FROM
+                `tmdb_info`
+            WHERE
+                `tmdb_id` = 0 AND `bangumi_id` = 0 AND `anilist_id` = 0
+            ORDER BY
+                `updated_at` DESC;"
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(names)
+    }
+}
+
+/// TMDB 上一季的集数，用于把绝对集数换算成季内集数
+#[derive(Debug, Clone)]
+pub struct TmdbSeason {
+    pub tmdb_id: i64,
+    pub season: i64,
+    pub episode_count: i64,
+    pub updated_at: i64,
+}
+
+impl TmdbSeason {
+    pub async fn list(tmdb_id: i64, pool: &Pool) -> Result<Vec<Self>> {
+        let ans = sqlx::query_as!(
+            Self,
+            r"SELECT
+                `tmdb_id`, `season`, `episode_count`, `updated_at`
+            FROM
+                `tmdb_season`
+            WHERE
+                `tmdb_id` = ?
+            ORDER BY
+                `season`;",
+            tmdb_id
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(ans)
+    }
+
+    /// 覆盖该剧已有的所有季
+    pub async fn replace_all(tmdb_id: i64, seasons: &[Self], pool: &Pool) -> Result<()> {
+        let mut tx = pool.begin().await?;
+        sqlx::query!("DELETE FROM `tmdb_season` WHERE `tmdb_id` = ?;", tmdb_id)
+            .execute(&mut *tx)
+            .await?;
+        for season in seasons {
+            sqlx::query!(
+                r"INSERT INTO `tmdb_season`
+                    (`tmdb_id`, `season`, `episode_count`, `updated_at`)
+                VALUES
+                    (?, ?, ?, ?);",
+                season.tmdb_id,
+                season.season,
+                season.episode_count,
+                season.updated_at,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
         Ok(())
     }
 }
@@ -11,6 +11,65 @@ pub struct TorrentInfo {
     pub resolution: String,
     pub language: String,
     pub tmdb_id: i64,
+    /// `episode` / `special` / `batch` / `movie`
+    pub kind: String,
+    pub episode_end: Option<i64>,
+    pub feed: String,
+    /// 被这个种子替换的旧种子
+    pub replaces: Option<i64>,
+    /// infohash，小写十六进制，添加前后都不知道时为空
+    pub hash: Option<String>,
+    /// 见 `TorrentState`
+    pub state: String,
+    /// 最近一次失败的原因
+    pub error: Option<String>,
+    /// 状态更新的时间戳
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TorrentState {
+    /// 已添加到下载器
+    Added,
+    Downloading,
+    /// 下载完成，还没链接
+    Completed,
+    /// 已链接到媒体库
+    Linked,
+    /// 添加或链接失败
+    Failed,
+    /// 已从下载器删除
+    Removed,
+    /// 被新版本替换，不再链接
+    Replaced,
+}
+impl TorrentState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Added => "added",
+            Self::Downloading => "downloading",
+            Self::Completed => "completed",
+            Self::Linked => "linked",
+            Self::Failed => "failed",
+            Self::Removed => "removed",
+            Self::Replaced => "replaced",
+        }
+    }
+}
+impl std::str::FromStr for TorrentState {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "added" => Ok(Self::Added),
+            "downloading" => Ok(Self::Downloading),
+            "completed" => Ok(Self::Completed),
+            "linked" => Ok(Self::Linked),
+            "failed" => Ok(Self::Failed),
+            "removed" => Ok(Self::Removed),
+            "replaced" => Ok(Self::Replaced),
+            _ => anyhow::bail!("unknown torrent state {s:?}"),
+        }
+    }
 }
 
 impl TorrentInfo {
@@ -19,11 +78,25 @@ impl TorrentInfo {
         thread_rng().gen_range(1..=i64::MAX)
     }
 
+    pub fn state(&self) -> Result<TorrentState> {
+        self.state.parse()
+    }
+
+    /// 剧集编号，如 `S01E05`、`S01E01-E12`，剧场版为 `Movie`
+    pub fn episode_label(&self) -> String {
+        match (self.kind.as_str(), self.episode_end) {
+            ("movie", _) => "Movie".to_string(),
+            ("batch", Some(end)) => format!("S{:02}E{:02}-E{:02}", self.season, self.episode, end),
+            _ => format!("S{:02}E{:02}", self.season, self.episode),
+        }
+    }
+
     pub async fn from_id(id: i64, pool: &Pool) -> Result<Self> {
         let s = sqlx::query_as!(
             Self,
             r"SELECT
-                `id`, `name`, `year`, `season`, `episode`, `fansub`, `resolution`, `language`, `tmdb_id`
+                `id`, `name`, `year`, `season`, `episode`, `fansub`, `resolution`, `language`, `tmdb_id`, `kind`, `episode_end`,
+                `feed`, `replaces`, `hash`, `state`, `error`, `updated_at`
             FROM
                 `torrent_info`
             WHERE
@@ -40,9 +113,10 @@ impl TorrentInfo {
     pub async fn insert(self, pool: &Pool) -> Result<()> {
         sqlx::query!(
             r"INSERT INTO `torrent_info`
-                (`id`, `name`, `year`, `season`, `episode`, `fansub`, `resolution`, `language`, `tmdb_id`)
+                (`id`, `name`, `year`, `season`, `episode`, `fansub`, `resolution`, `language`, `tmdb_id`, `kind`, `episode_end`,
+                `feed`, `replaces`, `hash`, `state`, `error`, `updated_at`)
             VALUES
-                (?, ?, ?, ?, ?, ?, ?, ?, ?);
+                (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);
             ",
             self.id,
             self.name,
@@ -52,10 +126,192 @@ impl TorrentInfo {
             self.fansub,
             self.resolution,
             self.language,
-            self.tmdb_id
+            self.tmdb_id,
+            self.kind,
+            self.episode_end,
+            self.feed,
+            self.replaces,
+            self.hash,
+            self.state,
+            self.error,
+            self.updated_at
         )
         .execute(pool)
         .await?;
         Ok(())
     }
+
+    pub async fn from_hash(hash: &str, pool: &Pool) -> Result<Self> {
+        let s = sqlx::query_as!(
+            Self,
+            r"SELECT
+                `id`, `name`, `year`, `season`, `episode`, `fansub`, `resolution`, `language`, `tmdb_id`, `kind`, `episode_end`,
+                `feed`, `replaces`, `hash`, `state`, `error`, `updated_at`
+            FROM
+                `torrent_info`
+            WHERE
+                `hash` = ?
+            LIMIT
+                1;",
+            hash
+        )
+        .fetch_one(pool)
+        .await?;
+        Ok(s)
+    }
+
+    pub async fn set_hash(id: i64, hash: &str, pool: &Pool) -> Result<()> {
+        sqlx::query!(
+            r"UPDATE `torrent_info` SET `hash` = ? WHERE `id` = ?;",
+            hash,
+            id
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// 在下载器里还没完成链接的种子，供轮询使用
+    pub async fn active(pool: &Pool) -> Result<Vec<Self>> {
+        let v = sqlx::query_as!(
+            Self,
+            r"SELECT
+                `id`, `name`, `year`, `season`, `episode`, `fansub`, `resolution`, `language`, `tmdb_id`, `kind`, `episode_end`,
+                `feed`, `replaces`, `hash`, `state`, `error`, `updated_at`
+            FROM
+                `torrent_info`
+            WHERE
+                `state` IN ('added', 'downloading', 'completed');"
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(v)
+    }
+
+    /// 没有从下载器删除的种子，供对账使用
+    pub async fn tracked(pool: &Pool) -> Result<Vec<Self>> {
+        let v = sqlx::query_as!(
+            Self,
+            r"SELECT
+                `id`, `name`, `year`, `season`, `episode`, `fansub`, `resolution`, `language`, `tmdb_id`, `kind`, `episode_end`,
+                `feed`, `replaces`, `hash`, `state`, `error`, `updated_at`
+            FROM
+                `torrent_info`
+            WHERE
+                `state` NOT IN ('removed', 'replaced');"
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(v)
+    }
+
+    pub async fn failed(pool: &Pool) -> Result<Vec<Self>> {
+        let v = sqlx::query_as!(
+            Self,
+            r"SELECT
+                `id`, `name`, `year`, `season`, `episode`, `fansub`, `resolution`, `language`, `tmdb_id`, `kind`, `episode_end`,
+                `feed`, `replaces`, `hash`, `state`, `error`, `updated_at`
+            FROM
+                `torrent_info`
+            WHERE
+                `state` = 'failed'
+            ORDER BY
+                `updated_at` DESC;"
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(v)
+    }
+
+    /// 失败时记录原因，其他状态清空原因
+    /// 还在下载中的改为 completed，返回是否修改了，用于 hook 和轮询之间只链接一次
+    pub async fn claim_completed(id: i64, pool: &Pool) -> Result<bool> {
+        let now = chrono::Utc::now().timestamp();
+        let r = sqlx::query!(
+            r"UPDATE `torrent_info` SET `state` = 'completed', `error` = NULL, `updated_at` = ?
+            WHERE `id` = ? AND `state` IN ('added', 'downloading');",
+            now,
+            id
+        )
+        .execute(pool)
+        .await?;
+        Ok(r.rows_affected() > 0)
+    }
+
+    pub async fn set_state(
+        id: i64,
+        state: TorrentState,
+        error: Option<&str>,
+        pool: &Pool,
+    ) -> Result<()> {
+        let state = state.as_str();
+        let now = chrono::Utc::now().timestamp();
+        sqlx::query!(
+            r"UPDATE `torrent_info` SET `state` = ?, `error` = ?, `updated_at` = ? WHERE `id` = ?;",
+            state,
+            error,
+            now,
+            id
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn pool() -> Pool {
+        // 内存数据库每个连接是独立的
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!().run(&pool).await.unwrap();
+        pool
+    }
+
+    fn torrent(id: i64, state: TorrentState) -> TorrentInfo {
+        TorrentInfo {
+            id,
+            name: "某剧".to_string(),
+            year: 0,
+            season: 1,
+            episode: 1,
+            fansub: "ANi".to_string(),
+            resolution: "1080p".to_string(),
+            language: "简日".to_string(),
+            tmdb_id: 0,
+            kind: "episode".to_string(),
+            episode_end: None,
+            feed: "feed".to_string(),
+            replaces: None,
+            hash: None,
+            state: state.as_str().to_string(),
+            error: None,
+            updated_at: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn replaced_not_tracked() {
+        let pool = pool().await;
+        torrent(1, TorrentState::Linked)
+            .insert(&pool)
+            .await
+            .unwrap();
+        torrent(2, TorrentState::Linked)
+            .insert(&pool)
+            .await
+            .unwrap();
+        TorrentInfo::set_state(1, TorrentState::Replaced, None, &pool)
+            .await
+            .unwrap();
+        let tracked = TorrentInfo::tracked(&pool).await.unwrap();
+        assert_eq!(tracked.iter().map(|t| t.id).collect::<Vec<_>>(), [2]);
+        assert!(!TorrentInfo::claim_completed(1, &pool).await.unwrap());
+    }
 }
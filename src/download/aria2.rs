@@ -0,0 +1,295 @@
+//! https://aria2.github.io/manual/en/html/aria2c.html#rpc-interface
+use super::{added_anyway, base64, http_client, join, DownloadClient, File, Torrent};
+use crate::{config::Aria2Config, request::AddTorrentRequest};
+use anyhow::{Context, Result};
+use serde::{de::DeserializeOwned, de::IgnoredAny, Deserialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+const KEYS: &[&str] = &[
+    "gid",
+    "infoHash",
+    "status",
+    "totalLength",
+    "completedLength",
+    "dir",
+    "files",
+    "bittorrent",
+];
+/// `tellWaiting` 和 `tellStopped` 一次取的数量
+const PAGE: usize = 1000;
+
+pub struct Aria2Client {
+    http: reqwest::Client,
+    config: Aria2Config,
+}
+
+impl Aria2Client {
+    pub fn new(config: Aria2Config) -> Result<Self> {
+        Ok(Self {
+            http: http_client(false)?,
+            config,
+        })
+    }
+
+    async fn call<R: DeserializeOwned>(&self, method: &str, mut params: Vec<Value>) -> Result<R> {
+        if let Some(secret) = &self.config.secret {
+            params.insert(0, json!(format!("token:{secret}")));
+        }
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": "rss-qb",
+            "method": method,
+            "params": params,
+        });
+        // 出错时状态码不是 200，但 body 里有错误信息
+        let resp = self
+            .http
+            .post(&self.config.url)
+            .json(&body)
+            .send()
+            .await?
+            .json::<Response>()
+            .await?;
+        if let Some(e) = resp.error {
+            anyhow::bail!("aria2 {method} failed: {} {}", e.code, e.message);
+        }
+        Ok(serde_json::from_value(resp.result)?)
+    }
+
+    /// 所有 bt 任务，包括等待中和已停止的
+    async fn tasks(&self) -> Result<Vec<Task>> {
+        let mut tasks: Vec<Task> = self.call("aria2.tellActive", vec![json!(KEYS)]).await?;
+        for method in ["aria2.tellWaiting", "aria2.tellStopped"] {
+            let more: Vec<Task> = self
+                .call(method, vec![json!(0), json!(PAGE), json!(KEYS)])
+                .await?;
+            tasks.extend(more);
+        }
+        tasks.retain(|t| !t.info_hash.is_empty() && t.status != "removed");
+        Ok(tasks)
+    }
+}
+
+#[derive(Deserialize)]
+struct Response {
+    #[serde(default)]
+    result: Value,
+    error: Option<RpcError>,
+}
+
+#[derive(Deserialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+/// aria2 的数字都以字符串返回
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct Task {
+    gid: String,
+    info_hash: String,
+    /// active, waiting, paused, error, complete, removed
+    status: String,
+    total_length: String,
+    completed_length: String,
+    dir: String,
+    files: Vec<TaskFile>,
+    bittorrent: Option<Bittorrent>,
+    /// 下载 .torrent 链接完成后自动添加的 bt 任务
+    followed_by: Vec<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct TaskFile {
+    /// 绝对路径
+    path: String,
+    length: String,
+    completed_length: String,
+}
+
+#[derive(Deserialize)]
+struct Bittorrent {
+    info: Option<BtInfo>,
+}
+
+#[derive(Deserialize)]
+struct BtInfo {
+    name: String,
+}
+
+fn num(s: &str) -> u64 {
+    s.parse().unwrap_or(0)
+}
+
+fn ratio(done: &str, total: &str) -> f64 {
+    match num(total) {
+        0 => 0.0,
+        total => num(done) as f64 / total as f64,
+    }
+}
+
+impl Task {
+    fn name(&self) -> String {
+        self.bittorrent
+            .as_ref()
+            .and_then(|b| b.info.as_ref())
+            .map(|i| i.name.clone())
+            .unwrap_or_else(|| self.info_hash.clone())
+    }
+}
+
+impl From<&Task> for Torrent {
+    fn from(t: &Task) -> Self {
+        let name = t.name();
+        let progress = ratio(&t.completed_length, &t.total_length);
+        Self {
+            hash: t.info_hash.to_lowercase(),
+            content_path: join(&t.dir, &name),
+            name,
+            progress,
+            // 做种时状态还是 active
+            done: t.status == "complete" || (num(&t.total_length) > 0 && progress >= 1.0),
+        }
+    }
+}
+
+/// aria2 的选项值都是字符串
+fn options(req: &AddTorrentRequest) -> HashMap<&'static str, String> {
+    let mut o = HashMap::new();
+    if let Some(dir) = &req.savepath {
+        o.insert("dir", dir.clone());
+    }
+    if let Some(paused) = req.paused {
+        o.insert("pause", paused.to_string());
+    }
+    if req.first_last_piece_prio == Some(true) {
+        o.insert("bt-prioritize-piece", "head,tail".to_string());
+    }
+    if let Some(up) = req.up_limit {
+        o.insert("max-upload-limit", up.to_string());
+    }
+    if let Some(dl) = req.dl_limit {
+        o.insert("max-download-limit", dl.to_string());
+    }
+    if let Some(ratio) = req.ratio_limit {
+        o.insert("seed-ratio", ratio.to_string());
+    }
+    if let Some(minutes) = req.seeding_time_limit.filter(|m| *m >= 0) {
+        o.insert("seed-time", minutes.to_string());
+    }
+    if req.skip_checking == Some(true) {
+        o.insert("bt-seed-unverified", "true".to_string());
+    }
+    o
+}
+
+impl DownloadClient for Aria2Client {
+    fn name(&self) -> &'static str {
+        "aria2"
+    }
+
+    async fn add(&self, req: AddTorrentRequest, hash: Option<&str>) -> Result<Option<String>> {
+        let options = json!(options(&req));
+        let mut gid = None;
+        for url in req.urls.iter() {
+            let params = vec![json!([url]), options.clone()];
+            match self.call::<String>("aria2.addUri", params).await {
+                Ok(g) => gid = Some(g),
+                Err(e) => return added_anyway(self, e, hash).await,
+            }
+        }
+        for torrent in req.torrents.iter() {
+            let params = vec![json!(base64(torrent)), json!([]), options.clone()];
+            match self.call::<String>("aria2.addTorrent", params).await {
+                Ok(g) => gid = Some(g),
+                Err(e) => return added_anyway(self, e, hash).await,
+            }
+        }
+        if let Some(hash) = hash {
+            return Ok(Some(hash.to_string()));
+        }
+        let Some(mut gid) = gid else {
+            return Ok(None);
+        };
+        // 下载 .torrent 链接时，种子文件下载完后 `followedBy` 里的任务才有 infohash
+        const TRIES: usize = 10;
+        let keys = json!(["infoHash", "status", "followedBy"]);
+        for _ in 0..TRIES {
+            let task: Task = self
+                .call("aria2.tellStatus", vec![json!(gid), keys.clone()])
+                .await?;
+            if !task.info_hash.is_empty() {
+                return Ok(Some(task.info_hash.to_lowercase()));
+            }
+            if let Some(next) = task.followed_by.into_iter().next() {
+                gid = next;
+                continue;
+            }
+            if matches!(task.status.as_str(), "error" | "removed") {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        }
+        warn!("infohash of aria2 task {gid} unknown");
+        Ok(None)
+    }
+
+    async fn list(&self) -> Result<Vec<Torrent>> {
+        Ok(self.tasks().await?.iter().map(Torrent::from).collect())
+    }
+
+    async fn files(&self, hash: &str) -> Result<Vec<File>> {
+        let task = self
+            .tasks()
+            .await?
+            .into_iter()
+            .find(|t| t.info_hash.eq_ignore_ascii_case(hash))
+            .with_context(|| format!("torrent {hash} not found"))?;
+        let dir = format!("{}/", task.dir.trim_end_matches('/'));
+        Ok(task
+            .files
+            .iter()
+            .map(|f| File {
+                path: f.path.strip_prefix(&dir).unwrap_or(&f.path).to_string(),
+                size: num(&f.length),
+                progress: ratio(&f.completed_length, &f.length),
+            })
+            .collect())
+    }
+
+    /// aria2 不会删除文件，需要时由我们删除
+    async fn delete(&self, hashes: &[&str], delete_files: bool) -> Result<()> {
+        let tasks = self.tasks().await?;
+        let tasks = tasks
+            .iter()
+            .filter(|t| hashes.iter().any(|h| t.info_hash.eq_ignore_ascii_case(h)));
+        for task in tasks {
+            if !matches!(task.status.as_str(), "complete" | "error") {
+                self.call::<IgnoredAny>("aria2.forceRemove", vec![json!(task.gid)])
+                    .await?;
+            }
+            // 刚移除的任务可能还没停下，结果删不掉也无妨
+            if let Err(e) = self
+                .call::<IgnoredAny>("aria2.removeDownloadResult", vec![json!(task.gid)])
+                .await
+            {
+                debug!("remove aria2 result {} failed: {e:#}", task.gid);
+            }
+            if delete_files {
+                let path = Torrent::from(task).content_path;
+                let r = match tokio::fs::metadata(&path).await {
+                    Ok(m) if m.is_dir() => tokio::fs::remove_dir_all(&path).await,
+                    Ok(_) => tokio::fs::remove_file(&path).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = r {
+                    warn!("delete {path} failed: {e}");
+                }
+            }
+        }
+        Ok(())
+    }
+}
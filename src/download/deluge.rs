@@ -0,0 +1,283 @@
+//! https://deluge.readthedocs.io/en/latest/reference/webapi.html
+use super::{added_anyway, base64, http_client, join, DownloadClient, File, Torrent};
+use crate::{config::DelugeConfig, request::AddTorrentRequest};
+use anyhow::{bail, Context, Result};
+use serde::{de::DeserializeOwned, de::IgnoredAny, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// 未登录的错误码
+const NOT_AUTHENTICATED: i64 = 1;
+const KEYS: &[&str] = &["name", "save_path", "progress", "is_finished"];
+
+pub struct DelugeClient {
+    http: reqwest::Client,
+    config: DelugeConfig,
+    id: AtomicU64,
+}
+
+impl DelugeClient {
+    pub async fn new(config: DelugeConfig) -> Result<Self> {
+        let this = Self {
+            http: http_client(true)?,
+            config,
+            id: AtomicU64::new(0),
+        };
+        this.login().await?;
+        info!("deluge logged in.");
+        Ok(this)
+    }
+
+    /// 登录 web ui，再连上 daemon
+    async fn login(&self) -> Result<()> {
+        let ok: bool = self
+            .call_once("auth.login", json!([self.config.password]))
+            .await?;
+        if !ok {
+            bail!("deluge login failed");
+        }
+        let connected: bool = self.call_once("web.connected", json!([])).await?;
+        if connected {
+            return Ok(());
+        }
+        let hosts: Vec<Vec<Value>> = self.call_once("web.get_hosts", json!([])).await?;
+        let host = hosts
+            .first()
+            .and_then(|h| h.first())
+            .and_then(Value::as_str)
+            .context("no deluge daemon configured in web ui")?
+            .to_string();
+        self.call_once::<IgnoredAny>("web.connect", json!([host]))
+            .await?;
+        Ok(())
+    }
+
+    async fn call_once<R: DeserializeOwned>(&self, method: &str, params: Value) -> Result<R> {
+        let body = Request {
+            method,
+            params,
+            id: self.id.fetch_add(1, Ordering::Relaxed),
+        };
+        let resp = self
+            .http
+            .post(&self.config.url)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Response>()
+            .await?;
+        if let Some(e) = resp.error {
+            return Err(e.into());
+        }
+        Ok(serde_json::from_value(resp.result)?)
+    }
+
+    /// 会话过期时重新登录并重试一次
+    async fn call<R: DeserializeOwned>(&self, method: &str, params: Value) -> Result<R> {
+        match self.call_once(method, params.clone()).await {
+            Err(e)
+                if e.downcast_ref::<RpcError>()
+                    .is_some_and(|e| e.code == NOT_AUTHENTICATED) =>
+            {
+                warn!("deluge session expired, logging in again");
+                self.login().await?;
+                self.call_once(method, params).await
+            }
+            r => r,
+        }
+    }
+
+    async fn status(&self, filter: Value) -> Result<Vec<Torrent>> {
+        let torrents: HashMap<String, DeTorrent> = self
+            .call("core.get_torrents_status", json!([filter, KEYS]))
+            .await?;
+        Ok(torrents
+            .into_iter()
+            .map(|(hash, t)| Torrent {
+                content_path: join(&t.save_path, &t.name),
+                hash,
+                name: t.name,
+                progress: t.progress / 100.0,
+                done: t.is_finished || t.progress >= 100.0,
+            })
+            .collect())
+    }
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+    method: &'a str,
+    params: Value,
+    id: u64,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    #[serde(default)]
+    result: Value,
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcError {
+    message: String,
+    #[serde(default)]
+    code: i64,
+}
+
+impl std::fmt::Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "deluge error {}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+/// 限速单位是 KiB/s
+#[derive(Serialize, Default)]
+struct AddOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    download_location: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    move_completed: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    move_completed_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    add_paused: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sequential_download: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prioritize_first_last_pieces: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_upload_speed: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_download_speed: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_at_ratio: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_ratio: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed_mode: Option<bool>,
+}
+
+impl From<&AddTorrentRequest> for AddOptions {
+    fn from(req: &AddTorrentRequest) -> Self {
+        // 有下载目录时先下到下载目录，完成后移到保存目录
+        let (download_location, move_completed_path) = match &req.download_path {
+            Some(download_path) => (Some(download_path.clone()), req.savepath.clone()),
+            None => (req.savepath.clone(), None),
+        };
+        Self {
+            download_location,
+            move_completed: move_completed_path.as_ref().map(|_| true),
+            move_completed_path,
+            add_paused: req.paused,
+            sequential_download: req.sequential_download,
+            prioritize_first_last_pieces: req.first_last_piece_prio,
+            max_upload_speed: req.up_limit.map(|b| b as f64 / 1024.0),
+            max_download_speed: req.dl_limit.map(|b| b as f64 / 1024.0),
+            stop_at_ratio: req.ratio_limit.map(|_| true),
+            stop_ratio: req.ratio_limit,
+            seed_mode: req.skip_checking,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct DeTorrent {
+    name: String,
+    save_path: String,
+    /// 0 到 100
+    progress: f64,
+    #[serde(default)]
+    is_finished: bool,
+}
+
+#[derive(Deserialize)]
+struct DeFiles {
+    files: Vec<DeFile>,
+    file_progress: Vec<f64>,
+}
+
+#[derive(Deserialize)]
+struct DeFile {
+    path: String,
+    size: u64,
+}
+
+impl DownloadClient for DelugeClient {
+    fn name(&self) -> &'static str {
+        "deluge"
+    }
+
+    async fn add(&self, req: AddTorrentRequest, hash: Option<&str>) -> Result<Option<String>> {
+        let options = serde_json::to_value(AddOptions::from(&req))?;
+        let mut added = None;
+        for url in req.urls.iter() {
+            let method = if url.starts_with("magnet:") {
+                "core.add_torrent_magnet"
+            } else {
+                "core.add_torrent_url"
+            };
+            match self.call(method, json!([url, options])).await {
+                Ok(h) => added = h,
+                Err(e) => return added_anyway(self, e, hash).await,
+            }
+        }
+        for (i, torrent) in req.torrents.iter().enumerate() {
+            let params = json!([format!("{i}.torrent"), base64(torrent), options]);
+            match self.call("core.add_torrent_file", params).await {
+                Ok(h) => added = h,
+                Err(e) => return added_anyway(self, e, hash).await,
+            }
+        }
+        // 重复添加时返回 null
+        match added {
+            Some(h) => Ok(Some(h)),
+            None => added_anyway(self, anyhow::anyhow!("deluge returned no hash"), hash).await,
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<Torrent>> {
+        self.status(json!({})).await
+    }
+
+    async fn get(&self, hashes: &[&str]) -> Result<Vec<Torrent>> {
+        self.status(json!({ "id": hashes })).await
+    }
+
+    async fn files(&self, hash: &str) -> Result<Vec<File>> {
+        let status: DeFiles = self
+            .call(
+                "core.get_torrent_status",
+                json!([hash, ["files", "file_progress"]]),
+            )
+            .await?;
+        Ok(status
+            .files
+            .into_iter()
+            .zip(status.file_progress)
+            .map(|(f, progress)| File {
+                path: f.path,
+                size: f.size,
+                progress,
+            })
+            .collect())
+    }
+
+    async fn delete(&self, hashes: &[&str], delete_files: bool) -> Result<()> {
+        self.call::<IgnoredAny>("core.remove_torrents", json!([hashes, delete_files]))
+            .await?;
+        Ok(())
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.call_once::<IgnoredAny>("auth.delete_session", json!([]))
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+//! 下载器的抽象，qb 之外支持 Transmission、Deluge 和 aria2
+use crate::{config::DownloaderConfig, request::AddTorrentRequest, QbClient};
+use anyhow::Result;
+
+mod aria2;
+mod deluge;
+mod qb;
+mod session;
+mod transmission;
+use aria2::Aria2Client;
+use deluge::DelugeClient;
+pub use session::Session;
+use transmission::TransmissionClient;
+
+/// 下载器中的种子
+#[derive(Debug, Clone)]
+pub struct Torrent {
+    /// infohash，小写十六进制
+    pub hash: String,
+    pub name: String,
+    /// 单文件时为文件路径，多文件时为种子的根目录
+    pub content_path: String,
+    /// 0 到 1
+    pub progress: f64,
+    /// 下载完成，做种中也算
+    pub done: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct File {
+    /// 相对于保存目录，`/` 分隔
+    pub path: String,
+    pub size: u64,
+    pub progress: f64,
+}
+
+/// 各下载器的公共操作。`AddTorrentRequest` 里下载器不支持的选项会被忽略
+///
+/// 只在单个任务里使用，不需要 `Send` 约束
+#[allow(async_fn_in_trait)]
+pub trait DownloadClient {
+    fn name(&self) -> &'static str;
+
+    /// 添加种子，返回 infohash。传入的 `hash` 已存在时视为成功
+    async fn add(&self, req: AddTorrentRequest, hash: Option<&str>) -> Result<Option<String>>;
+
+    async fn list(&self) -> Result<Vec<Torrent>>;
+
+    async fn get(&self, hashes: &[&str]) -> Result<Vec<Torrent>> {
+        let mut torrents = self.list().await?;
+        torrents.retain(|t| hashes.contains(&t.hash.as_str()));
+        Ok(torrents)
+    }
+
+    /// 已下载完成的种子，没有完成回调的下载器靠轮询这个
+    async fn completed(&self) -> Result<Vec<Torrent>> {
+        let mut torrents = self.list().await?;
+        torrents.retain(|t| t.done);
+        Ok(torrents)
+    }
+
+    async fn files(&self, hash: &str) -> Result<Vec<File>>;
+
+    /// `delete_files` 为 true 时同时删除下载的文件
+    async fn delete(&self, hashes: &[&str], delete_files: bool) -> Result<()>;
+
+    /// 退出前登出
+    async fn close(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// 添加报错但种子已经在下载器里时视为成功，如重复添加
+async fn added_anyway(
+    client: &impl DownloadClient,
+    err: anyhow::Error,
+    hash: Option<&str>,
+) -> Result<Option<String>> {
+    let Some(hash) = hash else {
+        return Err(err);
+    };
+    if client.get(&[hash]).await?.is_empty() {
+        return Err(err);
+    }
+    warn!(
+        "torrent {hash} already exists in {}: {err:#}",
+        client.name()
+    );
+    Ok(Some(hash.to_string()))
+}
+
+/// 非 qb 下载器用的 http 客户端
+fn http_client(cookie_store: bool) -> Result<reqwest::Client> {
+    Ok(reqwest::ClientBuilder::new()
+        .timeout(std::time::Duration::from_secs(10))
+        .cookie_store(cookie_store)
+        .build()?)
+}
+
+fn base64(bytes: &[u8]) -> String {
+    use base64::Engine;
+    base64::engine::general_purpose::STANDARD.encode(bytes)
+}
+
+fn join(dir: &str, name: &str) -> String {
+    std::path::Path::new(dir)
+        .join(name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+pub enum Downloader {
+    Qb(QbClient),
+    Transmission(TransmissionClient),
+    Deluge(DelugeClient),
+    Aria2(Aria2Client),
+}
+
+impl Downloader {
+    pub async fn new(config: &DownloaderConfig) -> Result<Self> {
+        Ok(match config {
+            DownloaderConfig::Qbittorrent(c) => {
+                Self::Qb(QbClient::new(c.base_url.clone(), c.auth()).await?)
+            }
+            DownloaderConfig::Transmission(c) => {
+                Self::Transmission(TransmissionClient::new(c.clone())?)
+            }
+            DownloaderConfig::Deluge(c) => Self::Deluge(DelugeClient::new(c.clone()).await?),
+            DownloaderConfig::Aria2(c) => Self::Aria2(Aria2Client::new(c.clone())?),
+        })
+    }
+}
+
+impl DownloadClient for Downloader {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Qb(c) => c.name(),
+            Self::Transmission(c) => c.name(),
+            Self::Deluge(c) => c.name(),
+            Self::Aria2(c) => c.name(),
+        }
+    }
+
+    async fn add(&self, req: AddTorrentRequest, hash: Option<&str>) -> Result<Option<String>> {
+        match self {
+            Self::Qb(c) => c.add(req, hash).await,
+            Self::Transmission(c) => c.add(req, hash).await,
+            Self::Deluge(c) => c.add(req, hash).await,
+            Self::Aria2(c) => c.add(req, hash).await,
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<Torrent>> {
+        match self {
+            Self::Qb(c) => c.list().await,
+            Self::Transmission(c) => c.list().await,
+            Self::Deluge(c) => c.list().await,
+            Self::Aria2(c) => c.list().await,
+        }
+    }
+
+    async fn get(&self, hashes: &[&str]) -> Result<Vec<Torrent>> {
+        match self {
+            Self::Qb(c) => c.get(hashes).await,
+            Self::Transmission(c) => c.get(hashes).await,
+            Self::Deluge(c) => c.get(hashes).await,
+            Self::Aria2(c) => c.get(hashes).await,
+        }
+    }
+
+    async fn completed(&self) -> Result<Vec<Torrent>> {
+        match self {
+            Self::Qb(c) => c.completed().await,
+            Self::Transmission(c) => c.completed().await,
+            Self::Deluge(c) => c.completed().await,
+            Self::Aria2(c) => c.completed().await,
+        }
+    }
+
+    async fn files(&self, hash: &str) -> Result<Vec<File>> {
+        match self {
+            Self::Qb(c) => DownloadClient::files(c, hash).await,
+            Self::Transmission(c) => c.files(hash).await,
+            Self::Deluge(c) => c.files(hash).await,
+            Self::Aria2(c) => c.files(hash).await,
+        }
+    }
+
+    async fn delete(&self, hashes: &[&str], delete_files: bool) -> Result<()> {
+        match self {
+            Self::Qb(c) => c.delete(hashes, delete_files).await,
+            Self::Transmission(c) => c.delete(hashes, delete_files).await,
+            Self::Deluge(c) => c.delete(hashes, delete_files).await,
+            Self::Aria2(c) => c.delete(hashes, delete_files).await,
+        }
+    }
+
+    async fn close(&self) -> Result<()> {
+        match self {
+            Self::Qb(c) => c.close().await,
+            Self::Transmission(c) => c.close().await,
+            Self::Deluge(c) => c.close().await,
+            Self::Aria2(c) => c.close().await,
+        }
+    }
+}
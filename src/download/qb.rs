@@ -0,0 +1,72 @@
+use super::{DownloadClient, File, Torrent};
+use crate::{
+    qb::request::{self, TorrentFilter},
+    QbClient,
+};
+use anyhow::Result;
+
+impl From<request::Torrent> for Torrent {
+    fn from(t: request::Torrent) -> Self {
+        Self {
+            done: t.progress >= 1.0,
+            hash: t.hash,
+            name: t.name,
+            content_path: t.content_path,
+            progress: t.progress,
+        }
+    }
+}
+
+impl DownloadClient for QbClient {
+    fn name(&self) -> &'static str {
+        "qbittorrent"
+    }
+
+    async fn add(
+        &self,
+        req: request::AddTorrentRequest,
+        hash: Option<&str>,
+    ) -> Result<Option<String>> {
+        self.add_torrent_verified(req, hash).await?;
+        Ok(hash.map(str::to_string))
+    }
+
+    async fn list(&self) -> Result<Vec<Torrent>> {
+        let torrents = self.torrents(&TorrentFilter::default()).await?;
+        Ok(torrents.into_iter().map(Torrent::from).collect())
+    }
+
+    async fn get(&self, hashes: &[&str]) -> Result<Vec<Torrent>> {
+        let torrents = self.torrents_by_hash(hashes).await?;
+        Ok(torrents.into_iter().map(Torrent::from).collect())
+    }
+
+    async fn completed(&self) -> Result<Vec<Torrent>> {
+        let filter = TorrentFilter {
+            filter: Some("completed".to_string()),
+            ..Default::default()
+        };
+        let torrents = self.torrents(&filter).await?;
+        Ok(torrents.into_iter().map(Torrent::from).collect())
+    }
+
+    async fn files(&self, hash: &str) -> Result<Vec<File>> {
+        let files = QbClient::files(self, hash).await?;
+        Ok(files
+            .into_iter()
+            .map(|f| File {
+                path: f.name,
+                size: f.size as u64,
+                progress: f.progress,
+            })
+            .collect())
+    }
+
+    async fn delete(&self, hashes: &[&str], delete_files: bool) -> Result<()> {
+        self.delete_torrents(hashes, delete_files).await
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.logout().await
+    }
+}
@@ -0,0 +1,59 @@
+use super::{DownloadClient, Downloader};
+use crate::config::DownloaderConfig;
+use anyhow::Result;
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::Mutex;
+
+/// runner 和 server 共用的下载器会话，每个下载器一个。配置不变时重载也沿用同一个会话
+#[derive(Default)]
+pub struct Session {
+    /// 按下载器名字，默认下载器的名字为空
+    clients: Mutex<HashMap<String, (DownloaderConfig, Arc<Downloader>)>>,
+}
+
+impl Session {
+    /// 配置变化时新建会话，旧会话在最后一个引用释放时登出
+    pub async fn get(&self, name: &str, config: &DownloaderConfig) -> Result<Arc<Downloader>> {
+        if let Some(client) = self.cached(name, config).await {
+            return Ok(client);
+        }
+        // 登录时不持有锁，一个下载器连不上不影响其他下载器
+        let client = Arc::new(Downloader::new(config).await?);
+        let mut clients = self.clients.lock().await;
+        if let Some((_, existing)) = clients.get(name).filter(|(c, _)| c == config) {
+            // 同时有别的任务登录好了，用先登录的
+            let existing = existing.clone();
+            drop(clients);
+            if let Err(e) = client.close().await {
+                warn!("{} logout failed: {e:?}", client.name());
+            }
+            return Ok(existing);
+        }
+        clients.insert(name.to_string(), (config.clone(), client.clone()));
+        Ok(client)
+    }
+
+    async fn cached(&self, name: &str, config: &DownloaderConfig) -> Option<Arc<Downloader>> {
+        let clients = self.clients.lock().await;
+        let (_, client) = clients.get(name).filter(|(c, _)| c == config)?;
+        Some(client.clone())
+    }
+
+    /// 重载后丢掉配置里已经没有的下载器
+    pub async fn retain(&self, names: &[&str]) {
+        self.clients
+            .lock()
+            .await
+            .retain(|name, _| names.contains(&name.as_str()));
+    }
+
+    /// 退出前调用，等待登出完成
+    pub async fn close(&self) {
+        let clients = std::mem::take(&mut *self.clients.lock().await);
+        for (_, client) in clients.into_values() {
+            if let Err(e) = client.close().await {
+                warn!("{} logout failed: {e:?}", client.name());
+            }
+        }
+    }
+}
@@ -0,0 +1,312 @@
+//! https://github.com/transmission/transmission/blob/main/docs/rpc-spec.md
+use super::{added_anyway, base64, http_client, join, DownloadClient, File, Torrent};
+use crate::{config::TransmissionConfig, request::AddTorrentRequest};
+use anyhow::{bail, Context, Result};
+use reqwest::StatusCode;
+use serde::{de::DeserializeOwned, de::IgnoredAny, Deserialize, Serialize};
+use std::sync::Mutex;
+
+const SESSION_HEADER: &str = "X-Transmission-Session-Id";
+const FIELDS: &[&str] = &["hashString", "name", "downloadDir", "percentDone", "files"];
+
+pub struct TransmissionClient {
+    http: reqwest::Client,
+    config: TransmissionConfig,
+    /// 服务端返回 409 时更新
+    session_id: Mutex<String>,
+}
+
+impl TransmissionClient {
+    pub fn new(config: TransmissionConfig) -> Result<Self> {
+        Ok(Self {
+            http: http_client(false)?,
+            config,
+            session_id: Mutex::new(String::new()),
+        })
+    }
+
+    async fn call<A: Serialize, R: DeserializeOwned>(
+        &self,
+        method: &str,
+        arguments: A,
+    ) -> Result<R> {
+        let body = Request { method, arguments };
+        // 第一次请求或 session 过期时返回 409 和新的 session id
+        for _ in 0..2 {
+            let session_id = self.session_id.lock().unwrap().clone();
+            let mut req = self
+                .http
+                .post(&self.config.url)
+                .header(SESSION_HEADER, session_id)
+                .json(&body);
+            if let Some(username) = &self.config.username {
+                req = req.basic_auth(username, self.config.password.as_ref());
+            }
+            let resp = req.send().await?;
+            if resp.status() == StatusCode::CONFLICT {
+                let id = resp
+                    .headers()
+                    .get(SESSION_HEADER)
+                    .context("no session id in 409 response")?
+                    .to_str()?;
+                *self.session_id.lock().unwrap() = id.to_string();
+                continue;
+            }
+            let resp = resp.error_for_status()?.json::<Response<R>>().await?;
+            if resp.result != "success" {
+                bail!("transmission {method} failed: {}", resp.result);
+            }
+            return resp.arguments.context("no arguments in response");
+        }
+        bail!("transmission rejected the session id")
+    }
+
+    async fn torrent_get(&self, ids: Option<&[&str]>) -> Result<Vec<TrTorrent>> {
+        let resp: TorrentGet = self
+            .call(
+                "torrent-get",
+                GetArgs {
+                    ids,
+                    fields: FIELDS,
+                },
+            )
+            .await?;
+        Ok(resp.torrents)
+    }
+}
+
+#[derive(Serialize)]
+struct Request<'a, A> {
+    method: &'a str,
+    arguments: A,
+}
+
+#[derive(Deserialize)]
+struct Response<R> {
+    result: String,
+    arguments: Option<R>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct AddArgs {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filename: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metainfo: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    download_dir: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    paused: Option<bool>,
+    /// transmission 4.0 起支持
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    labels: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct AddResult {
+    torrent_added: Option<Added>,
+    torrent_duplicate: Option<Added>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Added {
+    hash_string: String,
+}
+
+/// 限速单位是 KB/s，transmission 默认以 1000 为 K
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct SetArgs<'a> {
+    ids: &'a [&'a str],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed_ratio_limit: Option<f64>,
+    /// 1 为使用 `seedRatioLimit`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed_ratio_mode: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upload_limit: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upload_limited: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    download_limit: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    download_limited: Option<bool>,
+    /// transmission 4.1 起支持
+    #[serde(
+        rename = "sequential_download",
+        skip_serializing_if = "Option::is_none"
+    )]
+    sequential_download: Option<bool>,
+}
+
+#[derive(Serialize)]
+struct GetArgs<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ids: Option<&'a [&'a str]>,
+    fields: &'a [&'a str],
+}
+
+#[derive(Deserialize)]
+struct TorrentGet {
+    torrents: Vec<TrTorrent>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct TrTorrent {
+    hash_string: String,
+    name: String,
+    download_dir: String,
+    percent_done: f64,
+    files: Vec<TrFile>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct TrFile {
+    name: String,
+    length: u64,
+    bytes_completed: u64,
+}
+
+impl From<TrTorrent> for Torrent {
+    fn from(t: TrTorrent) -> Self {
+        Self {
+            content_path: join(&t.download_dir, &t.name),
+            hash: t.hash_string,
+            name: t.name,
+            progress: t.percent_done,
+            done: t.percent_done >= 1.0,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct RemoveArgs<'a> {
+    ids: &'a [&'a str],
+    delete_local_data: bool,
+}
+
+impl DownloadClient for TransmissionClient {
+    fn name(&self) -> &'static str {
+        "transmission"
+    }
+
+    async fn add(&self, req: AddTorrentRequest, hash: Option<&str>) -> Result<Option<String>> {
+        // 一次只能添加一个
+        let sources = req
+            .urls
+            .iter()
+            .map(|url| (Some(url.clone()), None))
+            .chain(req.torrents.iter().map(|t| (None, Some(base64(t)))));
+        let mut added = None;
+        for (filename, metainfo) in sources {
+            let args = AddArgs {
+                filename,
+                metainfo,
+                download_dir: req.savepath.clone(),
+                paused: req.paused,
+                labels: req.tags.clone(),
+            };
+            let result = match self.call::<_, AddResult>("torrent-add", args).await {
+                Ok(r) => r,
+                Err(e) => return added_anyway(self, e, hash).await,
+            };
+            let torrent = result
+                .torrent_added
+                .or(result.torrent_duplicate)
+                .context("no torrent in torrent-add response")?;
+            added = Some(torrent.hash_string.to_lowercase());
+        }
+        let Some(hash) = added else {
+            return Ok(None);
+        };
+
+        let ids = [hash.as_str()];
+        let mut set = SetArgs {
+            ids: &ids,
+            sequential_download: req.sequential_download,
+            ..Default::default()
+        };
+        if let Some(ratio) = req.ratio_limit {
+            set.seed_ratio_limit = Some(ratio);
+            set.seed_ratio_mode = Some(1);
+        }
+        if let Some(up) = req.up_limit {
+            set.upload_limit = Some(up / 1000);
+            set.upload_limited = Some(true);
+        }
+        if let Some(dl) = req.dl_limit {
+            set.download_limit = Some(dl / 1000);
+            set.download_limited = Some(true);
+        }
+        self.call::<_, IgnoredAny>("torrent-set", set).await?;
+        Ok(Some(hash))
+    }
+
+    async fn list(&self) -> Result<Vec<Torrent>> {
+        let torrents = self.torrent_get(None).await?;
+        Ok(torrents.into_iter().map(Torrent::from).collect())
+    }
+
+    async fn get(&self, hashes: &[&str]) -> Result<Vec<Torrent>> {
+        let torrents = self.torrent_get(Some(hashes)).await?;
+        Ok(torrents.into_iter().map(Torrent::from).collect())
+    }
+
+    async fn files(&self, hash: &str) -> Result<Vec<File>> {
+        let torrent = self
+            .torrent_get(Some(&[hash]))
+            .await?
+            .into_iter()
+            .next()
+            .with_context(|| format!("torrent {hash} not found"))?;
+        Ok(torrent
+            .files
+            .into_iter()
+            .map(|f| File {
+                progress: if f.length == 0 {
+                    1.0
+                } else {
+                    f.bytes_completed as f64 / f.length as f64
+                },
+                path: f.name,
+                size: f.length,
+            })
+            .collect())
+    }
+
+    async fn delete(&self, hashes: &[&str], delete_files: bool) -> Result<()> {
+        let args = RemoveArgs {
+            ids: hashes,
+            delete_local_data: delete_files,
+        };
+        self.call::<_, IgnoredAny>("torrent-remove", args).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_torrent_get() {
+        let resp: Response<TorrentGet> =
+            serde_json::from_str(include_str!("../../tests/transmission-torrent-get.json"))
+                .unwrap();
+        let torrents = resp.arguments.unwrap().torrents;
+        assert_eq!(torrents[0].files.len(), 2);
+        let t = Torrent::from(torrents.into_iter().next().unwrap());
+        assert_eq!(t.hash, "0631a44a87be60ee5a5a23ce416aabc902156320");
+        assert_eq!(
+            t.content_path,
+            "/downloads/[LoliHouse] 孤独摇滚 - 01 [WebRip 1080p HEVC-10bit AAC]"
+        );
+        assert!(t.done);
+    }
+}
@@ -1,7 +1,8 @@
-use crate::config::GptConfig;
+use crate::config::{GptConfig, OverBudget};
+use crate::db::{self, Pool};
 use anyhow::{bail, Context, Result};
 use async_openai::types::{
-    ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
+    ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs, CompletionUsage,
     CreateChatCompletionRequestArgs,
 };
 use async_openai::{config::OpenAIConfig, Client};
@@ -19,13 +20,58 @@ pub enum Recognized {
     Other,
 }
 
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ShowKind {
+    /// 单集
+    #[default]
+    Episode,
+    /// SP / OVA / OAD，归入第 0 季
+    Special,
+    /// 多集合集，`episode` 到 `episode_end`
+    Batch,
+    /// 剧场版
+    Movie,
+}
+impl ShowKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Episode => "episode",
+            Self::Special => "special",
+            Self::Batch => "batch",
+            Self::Movie => "movie",
+        }
+    }
+}
+impl std::str::FromStr for ShowKind {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "episode" => Ok(Self::Episode),
+            "special" => Ok(Self::Special),
+            "batch" => Ok(Self::Batch),
+            "movie" => Ok(Self::Movie),
+            _ => bail!("unknown show kind {s:?}"),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ShowInfo {
+    #[serde(default)]
+    pub kind: ShowKind,
     pub fansub: String,
     pub show: String,
+    #[serde(default = "default_season")]
     pub season: i64,
+    #[serde(default)]
     pub episode: i64,
-    pub resolution: String,
+    /// 合集的最后一集
+    #[serde(default)]
+    pub episode_end: Option<i64>,
+    /// 标题里没有时为空
+    #[serde(default)]
+    pub resolution: Option<String>,
     pub language: String,
     #[serde(skip_deserializing)]
     pub year: i64,
@@ -33,7 +79,39 @@ pub struct ShowInfo {
     pub tmdb_id: i64,
 }
 
-pub async fn get_episode_info(titles: &[String], config: &GptConfig) -> Result<Vec<Recognized>> {
+/// 本月花费是否已经超出预算
+pub async fn budget_exceeded(config: &GptConfig, pool: &Pool) -> Result<bool> {
+    use chrono::Datelike;
+    let Some(budget) = config.monthly_budget else {
+        return Ok(false);
+    };
+    let month_start = chrono::Utc::now()
+        .date_naive()
+        .with_day(1)
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .context("invalid month start")?
+        .and_utc()
+        .timestamp();
+    let cost = db::GptUsage::cost_since(month_start, pool).await?;
+    debug!("gpt cost this month: {cost:.4}, budget: {budget}");
+    Ok(cost >= budget)
+}
+
+fn default_season() -> i64 {
+    1
+}
+
+pub async fn get_episode_info(
+    titles: &[String],
+    config: &GptConfig,
+    feed: &str,
+    pool: &Pool,
+) -> Result<Vec<Recognized>> {
+    if config.over_budget == OverBudget::Local && budget_exceeded(config, pool).await? {
+        warn!("gpt monthly budget exceeded, fallback to local parser");
+        return Ok(titles.iter().map(|t| crate::parser::parse(t)).collect());
+    }
+
     let client_config = OpenAIConfig::new()
         .with_api_key(&config.token)
         .with_api_base(&config.url);
@@ -41,7 +119,9 @@ pub async fn get_episode_info(titles: &[String], config: &GptConfig) -> Result<V
 
     let mut futures = vec![];
     for chunk in titles.chunks(PER_REQ_SIZE) {
-        futures.push(get_episode_info_with_retry(chunk, &client, config));
+        futures.push(get_episode_info_with_retry(
+            chunk, &client, config, feed, pool,
+        ));
     }
     let re = futures::future::try_join_all(futures).await?;
     let re = re.into_iter().flatten().collect();
@@ -52,11 +132,38 @@ async fn get_episode_info_with_retry(
     titles: &[String],
     client: &GptClient,
     config: &GptConfig,
+    feed: &str,
+    pool: &Pool,
 ) -> Result<Vec<Recognized>> {
     for i in 0..=config.retry {
         let model = config.model(i);
         debug!("get_episode_info_with_retry, i={i}, model={model}");
-        let r = get_episode_info_raw(titles, client, model).await;
+        let t = std::time::Instant::now();
+        let mut usage = None;
+        let r = get_episode_info_raw(titles, client, model, &mut usage).await;
+        let outcome = match (&r, &usage) {
+            (Ok(_), _) => "ok",
+            (Err(_), Some(_)) => "invalid",
+            (Err(_), None) => "error",
+        };
+        let usage = usage.unwrap_or(CompletionUsage {
+            prompt_tokens: 0,
+            completion_tokens: 0,
+            total_tokens: 0,
+        });
+        let record = db::GptUsage {
+            feed: feed.to_string(),
+            model: model.to_string(),
+            prompt_tokens: usage.prompt_tokens as i64,
+            completion_tokens: usage.completion_tokens as i64,
+            latency_ms: t.elapsed().as_millis() as i64,
+            outcome: outcome.to_string(),
+            cost: config.cost(model, usage.prompt_tokens, usage.completion_tokens),
+            created_at: chrono::Utc::now().timestamp(),
+        };
+        if let Err(e) = record.insert(pool).await {
+            warn!("failed to record gpt usage: {e:#}");
+        }
         match r {
             Ok(r) => return Ok(r),
             Err(e) => {
@@ -78,6 +185,7 @@ async fn get_episode_info_raw(
     titles: &[String],
     client: &GptClient,
     model: &str,
+    usage: &mut Option<CompletionUsage>,
 ) -> Result<Vec<Recognized>> {
     debug!(
         "asking gpt {model} to recognize {} items: {titles:?}",
@@ -104,6 +212,7 @@ async fn get_episode_info_raw(
         .build()?;
     let mut response = client.chat().create(request).await?;
     info!("gpt response got, usage = {:?}", response.usage);
+    *usage = response.usage.take();
 
     let choice = response.choices.pop().context("no choice?")?;
     let content = choice.message.content.unwrap_or_default();
@@ -148,4 +257,26 @@ mod tests {
         ]"#;
         let _ = serde_json::from_str::<Vec<Recognized>>(s).unwrap();
     }
+
+    #[test]
+    fn parse_kinds() {
+        let s = r#"[
+            { "type": "show", "kind": "batch", "fansub": "VCB-Studio", "show": "孤独摇滚", "season": 1, "episode": 1, "episode_end": 12, "resolution": "1080p", "language": "简日" },
+            { "type": "show", "kind": "special", "fansub": "LoliHouse", "show": "孤独摇滚", "season": 0, "episode": 1, "resolution": "1080p", "language": "简日" },
+            { "type": "show", "kind": "movie", "fansub": "LoliHouse", "show": "孤独摇滚 剧场版", "resolution": "1080p", "language": "简日" },
+            { "type": "other" }
+        ]"#;
+        let r = serde_json::from_str::<Vec<Recognized>>(s).unwrap();
+        let Recognized::Show(batch) = &r[0] else {
+            panic!("not a show")
+        };
+        assert_eq!(batch.kind, ShowKind::Batch);
+        assert_eq!(batch.episode_end, Some(12));
+        let Recognized::Show(movie) = &r[2] else {
+            panic!("not a show")
+        };
+        assert_eq!(movie.kind, ShowKind::Movie);
+        assert_eq!(movie.season, 1);
+        assert!(matches!(r[3], Recognized::Other));
+    }
 }
@@ -3,14 +3,17 @@ extern crate log;
 
 pub mod config;
 pub mod db;
+pub mod download;
 pub mod qb;
 pub mod runner;
 pub mod series;
+pub mod torrent;
 
 pub use config::Config;
 pub use qb::{request, QbClient};
 
 pub mod gpt;
-pub mod tmdb;
+pub mod metadata;
+pub mod parser;
 
 pub mod server;
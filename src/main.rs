@@ -14,10 +14,13 @@ async fn main() -> Result<()> {
 
     let path = std::path::PathBuf::from_str("config.toml")?;
 
-    let server = rss_qb::server::main();
-    let runner = rss_qb::runner::run_watching(path);
-    tokio::select! {
+    let session = std::sync::Arc::new(rss_qb::download::Session::default());
+    let server = rss_qb::server::main(session.clone());
+    let runner = rss_qb::runner::run_watching(path, session.clone());
+    let r = tokio::select! {
         r = server => r,
         r = runner => r
-    }
+    };
+    session.close().await;
+    r
 }
@@ -0,0 +1,168 @@
+//! https://anilist.gitbook.io/anilist-apiv2-docs/
+use super::{Candidate, MediaType, Provider};
+use crate::config::Locale;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+const API_URL: &str = "https://graphql.anilist.co";
+
+const SEARCH_QUERY: &str = r#"
+query ($search: String, $format: [MediaFormat], $isAdult: Boolean) {
+  Page(perPage: 10) {
+    media(search: $search, type: ANIME, format_in: $format, isAdult: $isAdult) {
+      id
+      title { romaji english native }
+      synonyms
+      startDate { year }
+      coverImage { large }
+      countryOfOrigin
+    }
+  }
+}
+"#;
+
+pub struct AnilistClient {
+    http: reqwest::Client,
+    locale: Locale,
+}
+
+impl AnilistClient {
+    pub fn new(http: reqwest::Client, locale: Locale) -> Self {
+        Self { http, locale }
+    }
+}
+
+#[derive(Deserialize)]
+struct Response {
+    data: Option<Data>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Data {
+    page: Page,
+}
+
+#[derive(Deserialize)]
+struct Page {
+    media: Vec<Media>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Media {
+    id: i64,
+    title: Title,
+    #[serde(default)]
+    synonyms: Vec<String>,
+    start_date: Option<FuzzyDate>,
+    cover_image: Option<CoverImage>,
+    country_of_origin: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Title {
+    romaji: Option<String>,
+    english: Option<String>,
+    native: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct FuzzyDate {
+    year: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct CoverImage {
+    large: Option<String>,
+}
+
+impl Provider for AnilistClient {
+    fn name(&self) -> &'static str {
+        "anilist"
+    }
+
+    async fn search(&self, title: &str, media_type: MediaType) -> Result<Vec<Candidate>> {
+        let format = match media_type {
+            MediaType::Tv => vec!["TV", "TV_SHORT", "ONA", "OVA", "SPECIAL"],
+            MediaType::Movie => vec!["MOVIE"],
+        };
+        let body = self
+            .http
+            .post(API_URL)
+            .json(&serde_json::json!({
+                "query": SEARCH_QUERY,
+                "variables": {
+                    "search": title,
+                    "format": format,
+                    // null 时不过滤
+                    "isAdult": (!self.locale.include_adult).then_some(false),
+                },
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        parse_search(&body, &self.locale)
+    }
+}
+
+/// anilist 只有英文、罗马音和原名，日语用原名，其他语言用英文
+fn parse_search(body: &str, locale: &Locale) -> Result<Vec<Candidate>> {
+    let japanese = locale.is_original("ja");
+    let r: Response = serde_json::from_str(body)?;
+    let media = r.data.context("anilist returned no data")?.page.media;
+    let candidates = media
+        .into_iter()
+        .map(|m| {
+            let Title {
+                romaji,
+                english,
+                native,
+            } = m.title;
+            let name = native
+                .clone()
+                .filter(|_| japanese)
+                .or(english)
+                .or_else(|| romaji.clone())
+                .unwrap_or_default();
+            let mut alt_titles = m.synonyms;
+            alt_titles.extend(romaji);
+            Candidate {
+                id: m.id,
+                name,
+                original_name: native.unwrap_or_default(),
+                original_language: match m.country_of_origin.as_deref() {
+                    Some("CN") | Some("TW") => "zh",
+                    Some("KR") => "ko",
+                    _ => "ja",
+                }
+                .to_string(),
+                poster_path: m.cover_image.and_then(|c| c.large),
+                alt_titles,
+                year: m.start_date.and_then(|d| d.year),
+                animation: true,
+            }
+        })
+        .collect();
+    Ok(candidates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let s = include_str!("../../tests/anilist-search.json");
+        let r = parse_search(s, &Locale::default()).unwrap();
+        assert_eq!(r.len(), 2);
+        assert_eq!(r[0].id, 130003);
+        assert_eq!(r[0].name, "BOCCHI THE ROCK!");
+        assert_eq!(r[0].original_name, "ぼっち・ざ・ろっく！");
+        assert!(r[0].alt_titles.iter().any(|t| t == "孤独摇滚！"));
+        assert_eq!(r[0].year, Some(2022));
+        assert_eq!(r[1].name, "Bocchi the Rock! Recap Part 1");
+    }
+}
@@ -0,0 +1,149 @@
+//! https://bangumi.github.io/api/
+use super::{Candidate, MediaType, Provider};
+use crate::config::Locale;
+use anyhow::Result;
+use chrono::Datelike;
+use serde::{Deserialize, Serialize};
+
+const SEARCH_URL: &str = "https://api.bgm.tv/v0/search/subjects?limit=10";
+/// bangumi 要求带上 User-Agent
+const USER_AGENT: &str = "gwy15/rss-qb (https://github.com/gwy15/rss-qb)";
+
+/// 条目类型：动画
+const TYPE_ANIME: u8 = 2;
+/// 条目类型：三次元
+const TYPE_REAL: u8 = 6;
+
+pub struct BangumiClient {
+    http: reqwest::Client,
+    locale: Locale,
+}
+
+impl BangumiClient {
+    pub fn new(http: reqwest::Client, locale: Locale) -> Self {
+        Self { http, locale }
+    }
+}
+
+#[derive(Serialize)]
+struct SearchRequest<'a> {
+    keyword: &'a str,
+    filter: Filter,
+}
+
+#[derive(Serialize)]
+struct Filter {
+    r#type: Vec<u8>,
+    /// 不填时包含 R18 条目，false 时排除
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nsfw: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    #[serde(default)]
+    data: Vec<Subject>,
+}
+
+#[derive(Deserialize)]
+struct Subject {
+    id: i64,
+    r#type: u8,
+    name: String,
+    #[serde(default)]
+    name_cn: String,
+    #[serde(default)]
+    date: Option<String>,
+    #[serde(default)]
+    image: Option<String>,
+    #[serde(default)]
+    platform: Option<String>,
+}
+
+impl Provider for BangumiClient {
+    fn name(&self) -> &'static str {
+        "bangumi"
+    }
+
+    async fn search(&self, title: &str, media_type: MediaType) -> Result<Vec<Candidate>> {
+        // 三次元条目也一起搜，是否为动画交给打分
+        let body = self
+            .http
+            .post(SEARCH_URL)
+            .header(reqwest::header::USER_AGENT, USER_AGENT)
+            .json(&SearchRequest {
+                keyword: title,
+                filter: Filter {
+                    r#type: vec![TYPE_ANIME, TYPE_REAL],
+                    nsfw: (!self.locale.include_adult).then_some(false),
+                },
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        parse_search(&body, media_type, &self.locale)
+    }
+}
+
+/// bangumi 只有中文译名，其他语言用原名
+fn parse_search(body: &str, media_type: MediaType, locale: &Locale) -> Result<Vec<Candidate>> {
+    let chinese = locale.is_original("zh");
+    let r: SearchResponse = serde_json::from_str(body)?;
+    let candidates = r
+        .data
+        .into_iter()
+        .filter(|s| {
+            let movie = s.platform.as_deref() == Some("剧场版");
+            movie == (media_type == MediaType::Movie)
+        })
+        .map(|s| Candidate {
+            id: s.id,
+            name: if s.name_cn.is_empty() || !chinese {
+                s.name.clone()
+            } else {
+                s.name_cn
+            },
+            original_name: s.name,
+            original_language: "ja".to_string(),
+            poster_path: s.image.filter(|i| !i.is_empty()),
+            alt_titles: vec![],
+            year: s
+                .date
+                .and_then(|d| chrono::NaiveDate::parse_from_str(&d, "%Y-%m-%d").ok())
+                .map(|d| d.year()),
+            animation: s.r#type == TYPE_ANIME,
+        })
+        .collect();
+    Ok(candidates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let s = include_str!("../../tests/bangumi-search.json");
+        let locale = Locale::default();
+        let tv = parse_search(s, MediaType::Tv, &locale).unwrap();
+        assert_eq!(tv.len(), 1);
+        assert_eq!(tv[0].id, 328609);
+        assert_eq!(tv[0].name, "孤独摇滚！");
+        assert_eq!(tv[0].original_name, "ぼっち・ざ・ろっく！");
+        assert_eq!(tv[0].year, Some(2022));
+        assert!(tv[0].animation);
+
+        let movie = parse_search(s, MediaType::Movie, &locale).unwrap();
+        assert_eq!(movie.len(), 1);
+        assert_eq!(movie[0].year, Some(2024));
+
+        let locale = Locale {
+            language: "en-US".to_string(),
+            ..Default::default()
+        };
+        let tv = parse_search(s, MediaType::Tv, &locale).unwrap();
+        assert_eq!(tv[0].name, "ぼっち・ざ・ろっく！");
+    }
+}
@@ -0,0 +1,434 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::config::{Config, Locale, MetadataProvider};
+use crate::db::{self, Pool};
+use crate::gpt::ShowKind;
+use anyhow::Result;
+
+mod anilist;
+mod bangumi;
+mod rank;
+mod tmdb;
+use anilist::AnilistClient;
+use bangumi::BangumiClient;
+use rank::Candidate;
+pub use rank::Hint;
+use tmdb::TmdbClient;
+/// TMDB 条目类型，剧场版查电影，其余查剧集
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaType {
+    #[default]
+    Tv,
+    Movie,
+}
+impl MediaType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Tv => "tv",
+            Self::Movie => "movie",
+        }
+    }
+}
+impl From<ShowKind> for MediaType {
+    fn from(kind: ShowKind) -> Self {
+        match kind {
+            ShowKind::Movie => Self::Movie,
+            _ => Self::Tv,
+        }
+    }
+}
+
+/// 元数据源
+trait Provider {
+    fn name(&self) -> &'static str;
+
+    async fn search(&self, title: &str, media_type: MediaType) -> Result<Vec<Candidate>>;
+
+    /// 候选的别名，默认认为搜索结果里已经带上了
+    async fn alternative_titles(&self, _id: i64, _media_type: MediaType) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+}
+
+enum Source {
+    Tmdb(TmdbClient),
+    Bangumi(BangumiClient),
+    Anilist(AnilistClient),
+}
+impl Source {
+    fn new(
+        kind: MetadataProvider,
+        client: &reqwest::Client,
+        config: &Config,
+        locale: &Locale,
+    ) -> Result<Self> {
+        let client = client.clone();
+        let locale = locale.clone();
+        Ok(match kind {
+            MetadataProvider::Tmdb => {
+                Self::Tmdb(TmdbClient::new(client, &config.tmdb_secret, locale)?)
+            }
+            MetadataProvider::Bangumi => Self::Bangumi(BangumiClient::new(client, locale)),
+            MetadataProvider::Anilist => Self::Anilist(AnilistClient::new(client, locale)),
+        })
+    }
+
+    /// 在缓存条目上记录该源的 id
+    fn set_id(&self, show: &mut db::TmdbShow, id: i64) {
+        match self {
+            Self::Tmdb(_) => {
+                show.tmdb_id = id;
+                show.candidate_id = id;
+            }
+            Self::Bangumi(_) => show.bangumi_id = id,
+            Self::Anilist(_) => show.anilist_id = id,
+        }
+    }
+}
+impl Provider for Source {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Tmdb(c) => c.name(),
+            Self::Bangumi(c) => c.name(),
+            Self::Anilist(c) => c.name(),
+        }
+    }
+
+    async fn search(&self, title: &str, media_type: MediaType) -> Result<Vec<Candidate>> {
+        match self {
+            Self::Tmdb(c) => c.search(title, media_type).await,
+            Self::Bangumi(c) => c.search(title, media_type).await,
+            Self::Anilist(c) => c.search(title, media_type).await,
+        }
+    }
+
+    async fn alternative_titles(&self, id: i64, media_type: MediaType) -> Result<Vec<String>> {
+        match self {
+            Self::Tmdb(c) => c.alternative_titles(id, media_type).await,
+            Self::Bangumi(c) => c.alternative_titles(id, media_type).await,
+            Self::Anilist(c) => c.alternative_titles(id, media_type).await,
+        }
+    }
+}
+
+struct Providers<'a> {
+    /// 手动指定 id 和刷新缓存时用，不一定在 `sources` 里
+    tmdb: TmdbClient,
+    sources: Vec<Source>,
+    /// 缓存按语言区分
+    language: &'a str,
+}
+
+/// `titles` 的值为打分参考信息，缓存按 `locale` 的语言区分。
+/// 没有匹配的为 `None`，查询出错的不在结果里
+pub async fn get_info(
+    client: reqwest::Client,
+    config: &Config,
+    locale: &Locale,
+    titles: HashMap<(String, MediaType), Hint>,
+    pool: &Pool,
+) -> Result<HashMap<(String, MediaType), Option<db::TmdbShow>>> {
+    if titles.is_empty() {
+        return Ok(Default::default());
+    }
+    let providers = &Providers {
+        tmdb: TmdbClient::new(client.clone(), &config.tmdb_secret, locale.clone())?,
+        sources: config
+            .metadata
+            .providers
+            .iter()
+            .map(|&kind| Source::new(kind, &client, config, locale))
+            .collect::<Result<Vec<_>>>()?,
+        language: &locale.language,
+    };
+    let mut futures = vec![];
+    for ((title, media_type), hint) in titles {
+        let fut = async move {
+            // 单个剧名失败不影响其他剧名
+            match lookup(&title, media_type, hint, config, providers, pool).await {
+                Ok(Some(show)) => Some(((title, media_type), Some(show))),
+                Ok(None) => {
+                    info!("no metadata entry for {title}, use recognized name");
+                    Some(((title, media_type), None))
+                }
+                Err(e) => {
+                    warn!("metadata lookup for {title} failed: {e:#}");
+                    None
+                }
+            }
+        };
+        futures.push(fut);
+    }
+    let ans = futures::future::join_all(futures).await;
+    let ans = ans.into_iter().flatten().collect();
+    Ok(ans)
+}
+
+async fn lookup(
+    title: &str,
+    media_type: MediaType,
+    hint: Hint,
+    config: &Config,
+    providers: &Providers<'_>,
+    pool: &Pool,
+) -> Result<Option<db::TmdbShow>> {
+    let Providers {
+        tmdb,
+        sources,
+        language,
+    } = providers;
+    let now = chrono::Utc::now().timestamp();
+    let cached = db::TmdbShow::from_name(title, media_type.as_str(), language, pool).await?;
+    let ttl = config.tmdb.cache_ttl_days as i64 * 24 * 3600;
+    if let Some(tmdb_id) = config.tmdb_override(title, media_type) {
+        debug!("{title} is manually mapped to tmdb {tmdb_id}");
+        if let Some(known) = cached.filter(|k| k.tmdb_id == tmdb_id && now - k.updated_at < ttl) {
+            return Ok(Some(known));
+        }
+        let show = tmdb.details(tmdb_id, media_type, pool).await?;
+        show.save_with(title, media_type.as_str(), language, pool)
+            .await?;
+        return Ok(Some(show));
+    }
+    if let Some(known) = cached {
+        if known.is_matched() {
+            if now - known.updated_at < ttl {
+                return Ok(Some(known));
+            }
+            // 过期后 TMDB 条目按 id 刷新，其他源重新搜索
+            debug!("metadata cache of {title} expired, refreshing");
+            let fresh = match sources.first() {
+                Some(Source::Tmdb(_)) if known.tmdb_id != 0 => tmdb
+                    .details(known.tmdb_id, media_type, pool)
+                    .await
+                    .map(|fresh| db::TmdbShow {
+                        confidence: known.confidence,
+                        bangumi_id: known.bangumi_id,
+                        anilist_id: known.anilist_id,
+                        ..fresh
+                    }),
+                _ => search(title, media_type, hint, config, sources).await,
+            };
+            return match fresh {
+                Ok(fresh) if fresh.is_matched() => {
+                    fresh
+                        .save_with(title, media_type.as_str(), language, pool)
+                        .await?;
+                    Ok(Some(fresh))
+                }
+                Ok(_) => {
+                    warn!("refresh {title} found no match, use cached");
+                    Ok(Some(known))
+                }
+                Err(e) => {
+                    warn!("refresh {title} failed, use cached: {e:#}");
+                    Ok(Some(known))
+                }
+            };
+        }
+        if now - known.updated_at < config.tmdb.unmatched_retry_hours as i64 * 3600 {
+            return Ok(None);
+        }
+        debug!("retry searching unmatched {title}");
+    }
+    let show = search(title, media_type, hint, config, sources).await?;
+    show.save_with(title, media_type.as_str(), language, pool)
+        .await?;
+    Ok(show.is_matched().then_some(show))
+}
+
+/// 按配置顺序搜索各个源，名字等信息取第一个匹配上的源，其余的源只记录 id。
+/// 有源出错且都没有匹配时返回错误，不缓存
+async fn search(
+    title: &str,
+    media_type: MediaType,
+    hint: Hint,
+    config: &Config,
+    sources: &[Source],
+) -> Result<db::TmdbShow> {
+    let mut show: Option<db::TmdbShow> = None;
+    let mut unmatched = db::TmdbShow::unmatched(title);
+    let mut failed = false;
+    for source in sources {
+        let provider = source.name();
+        let ranked = match source.search(title, media_type).await {
+            Ok(candidates) => rank(source, title, media_type, candidates, hint).await,
+            Err(e) => Err(e),
+        };
+        let ranked = match ranked {
+            Ok(r) => r,
+            Err(e) => {
+                warn!("search {title} on {provider} failed: {e:#}");
+                failed = true;
+                continue;
+            }
+        };
+        match ranked {
+            Some((c, confidence)) if confidence >= config.tmdb.min_confidence => {
+                debug!(
+                    "{title} matched {provider} {} {}, confidence {confidence:.2}",
+                    c.id, c.name
+                );
+                match show.as_mut() {
+                    Some(show) => source.set_id(show, c.id),
+                    None => {
+                        let id = c.id;
+                        let mut s = to_show(c, confidence);
+                        source.set_id(&mut s, id);
+                        show = Some(s);
+                    }
+                }
+            }
+            Some((c, confidence)) => {
+                warn!(
+                    "{title} best {provider} candidate {} {} has low confidence {confidence:.2}, not accepted",
+                    c.id, c.name
+                );
+                if matches!(source, Source::Tmdb(_)) {
+                    unmatched.confidence = confidence;
+                    unmatched.candidate_id = c.id;
+                }
+            }
+            None => {}
+        }
+    }
+    match show {
+        Some(show) => Ok(show),
+        None if failed => anyhow::bail!("no provider matched {title} and some failed"),
+        None => Ok(unmatched),
+    }
+}
+
+/// 需要请求别名的候选数量
+const ALT_TITLE_CANDIDATES: usize = 3;
+
+/// 返回最佳候选和置信度。名字不够像时，再用前几个候选的别名重新打分
+async fn rank(
+    provider: &impl Provider,
+    title: &str,
+    media_type: MediaType,
+    candidates: Vec<Candidate>,
+    hint: Hint,
+) -> Result<Option<(Candidate, f64)>> {
+    let mut scored = candidates
+        .into_iter()
+        .map(|c| (rank::score(title, &c, hint), c))
+        .collect::<Vec<_>>();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    if scored.first().is_some_and(|(_, c)| {
+        rank::similarity(title, &c.name).max(rank::similarity(title, &c.original_name)) < 1.0
+    }) {
+        for (score, c) in scored.iter_mut().take(ALT_TITLE_CANDIDATES) {
+            let alt_titles = provider.alternative_titles(c.id, media_type).await?;
+            c.alt_titles.extend(alt_titles);
+            *score = rank::score(title, c, hint);
+        }
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    }
+    Ok(scored.into_iter().next().map(|(score, c)| (c, score)))
+}
+
+/// 各源的 id 由调用方填写
+fn to_show(c: Candidate, confidence: f64) -> db::TmdbShow {
+    db::TmdbShow {
+        tmdb_id: 0,
+        tmdb_name: c.name,
+        year: c.year.unwrap_or_default() as i64,
+        updated_at: chrono::Utc::now().timestamp(),
+        confidence,
+        candidate_id: 0,
+        original_name: c.original_name,
+        poster_path: c.poster_path,
+        bangumi_id: 0,
+        anilist_id: 0,
+    }
+}
+
+/// 季集数缓存的有效期
+const SEASON_TTL_S: i64 = 24 * 3600;
+
+/// 获取各剧的季列表，优先使用数据库缓存
+pub async fn get_seasons(
+    client: reqwest::Client,
+    secret: &str,
+    tmdb_ids: HashSet<i64>,
+    pool: &Pool,
+) -> Result<HashMap<i64, Vec<db::TmdbSeason>>> {
+    if tmdb_ids.is_empty() {
+        return Ok(Default::default());
+    }
+    let client = &TmdbClient::new(client, secret, Locale::default())?;
+    let mut futures = vec![];
+    for tmdb_id in tmdb_ids {
+        let fut = async move {
+            let cached = db::TmdbSeason::list(tmdb_id, pool).await?;
+            let now = chrono::Utc::now().timestamp();
+            if !cached.is_empty() && cached.iter().all(|s| now - s.updated_at < SEASON_TTL_S) {
+                return Ok((tmdb_id, cached));
+            }
+            if let Err(e) = client.details(tmdb_id, MediaType::Tv, pool).await {
+                warn!("get tv show {tmdb_id} details failed, use cached seasons: {e:#}");
+                return Ok((tmdb_id, cached));
+            }
+            let seasons = db::TmdbSeason::list(tmdb_id, pool).await?;
+            anyhow::Ok((tmdb_id, seasons))
+        };
+        futures.push(fut);
+    }
+    let ans = futures::future::try_join_all(futures).await?;
+    Ok(ans.into_iter().collect())
+}
+
+/// 集数超出了该季的集数，并且超过了之前各季的总集数时，按绝对集数从第一季开始换算成 (季, 集)
+pub fn remap_absolute(seasons: &[db::TmdbSeason], season: i64, episode: i64) -> Option<(i64, i64)> {
+    let count = seasons.iter().find(|s| s.season == season)?.episode_count;
+    let before: i64 = seasons
+        .iter()
+        .filter(|s| s.season >= 1 && s.season < season)
+        .map(|s| s.episode_count)
+        .sum();
+    if episode <= count || episode <= before {
+        return None;
+    }
+    let mut rest = episode;
+    for s in seasons.iter().filter(|s| s.season >= 1) {
+        if rest <= s.episode_count {
+            return Some((s.season, rest));
+        }
+        rest -= s.episode_count;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seasons(counts: &[(i64, i64)]) -> Vec<db::TmdbSeason> {
+        counts
+            .iter()
+            .map(|&(season, episode_count)| db::TmdbSeason {
+                tmdb_id: 1,
+                season,
+                episode_count,
+                updated_at: 0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn remap() {
+        let s = seasons(&[(0, 3), (1, 24), (2, 12), (3, 13)]);
+        assert_eq!(remap_absolute(&s, 1, 12), None);
+        assert_eq!(remap_absolute(&s, 1, 25), Some((2, 1)));
+        assert_eq!(remap_absolute(&s, 2, 25), Some((2, 1)));
+        assert_eq!(remap_absolute(&s, 1, 40), Some((3, 4)));
+        assert_eq!(remap_absolute(&s, 1, 100), None);
+        assert_eq!(remap_absolute(&s, 5, 1), None);
+        // 不超过之前各季的总集数时不是绝对集数
+        assert_eq!(remap_absolute(&s, 2, 13), None);
+        assert_eq!(remap_absolute(&s, 3, 30), None);
+        assert_eq!(remap_absolute(&s, 3, 40), Some((3, 4)));
+    }
+}
@@ -0,0 +1,137 @@
+//! 元数据搜索结果打分
+
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub id: i64,
+    pub name: String,
+    pub original_name: String,
+    pub original_language: String,
+    pub poster_path: Option<String>,
+    /// 别名，只在需要时才请求
+    pub alt_titles: Vec<String>,
+    pub year: Option<i32>,
+    /// 是否为动画
+    pub animation: bool,
+}
+
+/// 打分的参考信息
+#[derive(Debug, Clone, Copy)]
+pub struct Hint {
+    /// 种子发布的年份
+    pub release_year: i32,
+    /// 是否为动画 feed
+    pub anime: bool,
+}
+
+/// 0 ~ 1 的置信度
+pub fn score(query: &str, c: &Candidate, hint: Hint) -> f64 {
+    let title = std::iter::once(&c.name)
+        .chain(std::iter::once(&c.original_name))
+        .chain(c.alt_titles.iter())
+        .map(|t| similarity(query, t))
+        .fold(0.0, f64::max);
+
+    // 首播不可能晚于发布，长寿剧的首播可以早很多年
+    let year = match c.year {
+        None => 0.5,
+        Some(y) if y > hint.release_year => 0.0,
+        Some(y) => {
+            let diff = (hint.release_year - y) as f64;
+            if diff <= 1.0 {
+                1.0
+            } else {
+                (1.0 - 0.1 * diff).max(0.3)
+            }
+        }
+    };
+
+    if hint.anime {
+        let mut anime = 0.0;
+        if c.animation {
+            anime += 0.7;
+        }
+        if matches!(c.original_language.as_str(), "ja" | "zh") {
+            anime += 0.3;
+        }
+        title * 0.6 + year * 0.2 + anime * 0.2
+    } else {
+        title * 0.75 + year * 0.25
+    }
+}
+
+fn normalize(s: &str) -> Vec<char> {
+    s.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+/// 基于编辑距离的相似度，一方包含另一方时至少为 0.8
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (normalize(a), normalize(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let ratio = 1.0 - levenshtein(&a, &b) as f64 / a.len().max(b.len()) as f64;
+    let contains = |x: &[char], y: &[char]| x.windows(y.len()).any(|w| w == y);
+    if contains(&a, &b) || contains(&b, &a) {
+        ratio.max(0.8)
+    } else {
+        ratio
+    }
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    let mut cur = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        cur[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != cb);
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(id: i64, name: &str, year: i32, animation: bool) -> Candidate {
+        Candidate {
+            id,
+            name: name.to_string(),
+            original_name: String::new(),
+            original_language: "ja".to_string(),
+            poster_path: None,
+            alt_titles: vec![],
+            year: Some(year),
+            animation,
+        }
+    }
+
+    #[test]
+    fn similar() {
+        assert_eq!(similarity("孤独摇滚！", "孤独摇滚"), 1.0);
+        assert!(similarity("孤独摇滚 第二季", "孤独摇滚") >= 0.8);
+        assert!(similarity("秒杀外挂", "间谍过家家") < 0.2);
+    }
+
+    #[test]
+    fn prefer_anime_of_right_year() {
+        let hint = Hint {
+            release_year: 2024,
+            anime: true,
+        };
+        let live_action = Candidate {
+            original_language: "en".to_string(),
+            ..candidate(1, "怪兽8号", 2024, false)
+        };
+        let anime = candidate(2, "怪兽8号", 2024, true);
+        let future = candidate(3, "怪兽8号", 2026, true);
+        assert!(score("怪兽8号", &anime, hint) > score("怪兽8号", &live_action, hint));
+        assert!(score("怪兽8号", &anime, hint) > score("怪兽8号", &future, hint));
+    }
+}
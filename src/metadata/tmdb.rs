@@ -0,0 +1,268 @@
+use super::{Candidate, MediaType, Provider};
+use crate::config::Locale;
+use crate::db::{self, Pool};
+use anyhow::Result;
+use chrono::Datelike;
+use tmdb_api::prelude::Command as _;
+
+/// TMDB 的动画类型 id
+const ANIMATION_GENRE: u64 = 16;
+
+pub struct TmdbClient {
+    api: tmdb_api::Client,
+    http: reqwest::Client,
+    secret: String,
+    locale: Locale,
+}
+
+impl TmdbClient {
+    pub fn new(client: reqwest::Client, secret: &str, locale: Locale) -> Result<Self> {
+        let api = tmdb_api::client::ClientBuilder::default()
+            .with_base_url("https://api.themoviedb.org/3")
+            .with_reqwest_client(client.clone())
+            .with_api_key(secret.to_string())
+            .build()?;
+        Ok(Self {
+            api,
+            http: client,
+            secret: secret.to_string(),
+            locale,
+        })
+    }
+
+    /// 没有译名时 TMDB 返回原名
+    fn untranslated(&self, name: &str, original_name: &str, original_language: &str) -> bool {
+        self.locale.fallback_language.is_some()
+            && name == original_name
+            && !self.locale.is_original(original_language)
+    }
+
+    /// 按 id 获取条目，剧集会同时更新季列表
+    pub async fn details(
+        &self,
+        tmdb_id: i64,
+        media_type: MediaType,
+        pool: &Pool,
+    ) -> Result<db::TmdbShow> {
+        let language = &self.locale.language;
+        let (mut name, original_name, original_language, poster_path, year) = match media_type {
+            MediaType::Tv => {
+                let r = self.tv_details(tmdb_id, language).await?;
+                let now = chrono::Utc::now().timestamp();
+                let seasons = r
+                    .seasons
+                    .iter()
+                    .map(|s| db::TmdbSeason {
+                        tmdb_id,
+                        season: s.inner.season_number as i64,
+                        episode_count: s.episode_count as i64,
+                        updated_at: now,
+                    })
+                    .collect::<Vec<_>>();
+                db::TmdbSeason::replace_all(tmdb_id, &seasons, pool).await?;
+                let r = r.inner;
+                (
+                    r.name,
+                    r.original_name,
+                    r.original_language,
+                    r.poster_path,
+                    r.first_air_date,
+                )
+            }
+            MediaType::Movie => {
+                let r = self.movie_details(tmdb_id, language).await?;
+                (
+                    r.title,
+                    r.original_title,
+                    r.original_language,
+                    r.poster_path,
+                    r.release_date,
+                )
+            }
+        };
+        if self.untranslated(&name, &original_name, &original_language) {
+            let fallback = self.locale.fallback_language.as_deref().unwrap_or_default();
+            name = match media_type {
+                MediaType::Tv => self.tv_details(tmdb_id, fallback).await?.inner.name,
+                MediaType::Movie => self.movie_details(tmdb_id, fallback).await?.title,
+            };
+        }
+        Ok(db::TmdbShow {
+            tmdb_id,
+            tmdb_name: name,
+            year: year.unwrap_or_default().year() as i64,
+            updated_at: chrono::Utc::now().timestamp(),
+            confidence: 1.0,
+            candidate_id: tmdb_id,
+            original_name,
+            poster_path,
+            bangumi_id: 0,
+            anilist_id: 0,
+        })
+    }
+
+    async fn tv_details(&self, tmdb_id: i64, language: &str) -> Result<tmdb_api::tvshow::TVShow> {
+        let cmd = tmdb_api::tvshow::details::TVShowDetails::new(tmdb_id as u64)
+            .with_language(Some(language.to_string()));
+        match cmd.execute(&self.api).await {
+            Ok(r) => Ok(r),
+            Err(e) => anyhow::bail!("get tv show {tmdb_id} failed: {:?}", e),
+        }
+    }
+
+    async fn movie_details(
+        &self,
+        tmdb_id: i64,
+        language: &str,
+    ) -> Result<tmdb_api::movie::MovieBase> {
+        let cmd = tmdb_api::movie::details::MovieDetails::new(tmdb_id as u64)
+            .with_language(Some(language.to_string()));
+        match cmd.execute(&self.api).await {
+            Ok(r) => Ok(r.inner),
+            Err(e) => anyhow::bail!("get movie {tmdb_id} failed: {:?}", e),
+        }
+    }
+
+    async fn search_in(
+        &self,
+        title: &str,
+        media_type: MediaType,
+        language: &str,
+    ) -> Result<Vec<Candidate>> {
+        let language = Some(language.to_string());
+        let include_adult = self.locale.include_adult;
+        let candidates = match media_type {
+            MediaType::Tv => {
+                let cmd = tmdb_api::tvshow::search::TVShowSearch::new(title.to_string())
+                    .with_language(language)
+                    .with_include_adult(include_adult);
+                match cmd.execute(&self.api).await {
+                    Ok(r) => tv_candidates(r.results),
+                    Err(e) => anyhow::bail!("search tv show failed: {:?}", e),
+                }
+            }
+            MediaType::Movie => {
+                let cmd = tmdb_api::movie::search::MovieSearch::new(title.to_string())
+                    .with_language(language)
+                    .with_include_adult(include_adult)
+                    .with_region(self.locale.region.clone());
+                match cmd.execute(&self.api).await {
+                    Ok(r) => movie_candidates(r.results),
+                    Err(e) => anyhow::bail!("search movie failed: {:?}", e),
+                }
+            }
+        };
+        Ok(candidates)
+    }
+}
+
+impl Provider for TmdbClient {
+    fn name(&self) -> &'static str {
+        "tmdb"
+    }
+
+    async fn search(&self, title: &str, media_type: MediaType) -> Result<Vec<Candidate>> {
+        let mut candidates = self
+            .search_in(title, media_type, &self.locale.language)
+            .await?;
+        let untranslated =
+            |c: &Candidate| self.untranslated(&c.name, &c.original_name, &c.original_language);
+        if let Some(fallback) = &self.locale.fallback_language {
+            if candidates.iter().any(untranslated) {
+                let names = self
+                    .search_in(title, media_type, fallback)
+                    .await?
+                    .into_iter()
+                    .map(|c| (c.id, c.name))
+                    .collect::<std::collections::HashMap<_, _>>();
+                for c in candidates.iter_mut() {
+                    if untranslated(c) {
+                        if let Some(name) = names.get(&c.id) {
+                            c.name = name.clone();
+                        }
+                    }
+                }
+            }
+        }
+        Ok(candidates)
+    }
+
+    async fn alternative_titles(&self, id: i64, media_type: MediaType) -> Result<Vec<String>> {
+        #[derive(serde::Deserialize)]
+        struct Title {
+            title: String,
+        }
+        #[derive(serde::Deserialize)]
+        struct Titles {
+            // 剧集为 results，电影为 titles
+            #[serde(default, alias = "titles")]
+            results: Vec<Title>,
+        }
+        let url = format!(
+            "https://api.themoviedb.org/3/{}/{id}/alternative_titles",
+            media_type.as_str()
+        );
+        let r = self
+            .http
+            .get(url)
+            .query(&[("api_key", &self.secret)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Titles>()
+            .await?;
+        Ok(r.results.into_iter().map(|t| t.title).collect())
+    }
+}
+
+fn tv_candidates(results: Vec<tmdb_api::tvshow::TVShowShort>) -> Vec<Candidate> {
+    results
+        .into_iter()
+        .map(|r| Candidate {
+            id: r.inner.id as i64,
+            name: r.inner.name,
+            original_name: r.inner.original_name,
+            original_language: r.inner.original_language,
+            poster_path: r.inner.poster_path,
+            alt_titles: vec![],
+            year: r.inner.first_air_date.map(|d| d.year()),
+            animation: r.genre_ids.contains(&ANIMATION_GENRE),
+        })
+        .collect()
+}
+
+fn movie_candidates(results: Vec<tmdb_api::movie::MovieShort>) -> Vec<Candidate> {
+    results
+        .into_iter()
+        .map(|r| Candidate {
+            id: r.inner.id as i64,
+            name: r.inner.title,
+            original_name: r.inner.original_title,
+            original_language: r.inner.original_language,
+            poster_path: r.inner.poster_path,
+            alt_titles: vec![],
+            year: r.inner.release_date.map(|d| d.year()),
+            animation: r.genre_ids.contains(&ANIMATION_GENRE),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_search() {
+        let s = include_str!("../../tests/tmdb-search-tv.json");
+        let r: tmdb_api::common::PaginatedResult<tmdb_api::tvshow::TVShowShort> =
+            serde_json::from_str(s).unwrap();
+        let candidates = tv_candidates(r.results);
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].id, 119100);
+        assert_eq!(candidates[0].name, "孤独摇滚！");
+        assert_eq!(candidates[0].original_name, "ぼっち・ざ・ろっく！");
+        assert_eq!(candidates[0].year, Some(2022));
+        assert!(candidates[0].animation);
+        assert!(!candidates[1].animation);
+    }
+}
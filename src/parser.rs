@@ -0,0 +1,246 @@
+//! 不依赖 GPT 的本地标题解析，只覆盖常见字幕组的命名格式。
+use crate::gpt::{Recognized, ShowInfo, ShowKind};
+use regex::Regex;
+use std::sync::OnceLock;
+
+macro_rules! regex {
+    ($re:literal) => {{
+        static RE: OnceLock<Regex> = OnceLock::new();
+        RE.get_or_init(|| Regex::new($re).unwrap())
+    }};
+}
+
+pub fn parse(title: &str) -> Recognized {
+    match parse_show(title) {
+        Some(info) => Recognized::Show(info),
+        None => Recognized::Other,
+    }
+}
+
+/// 分辨率的高低，如 `1080p`、`1920x1080`、`4K`，无法识别时为 0
+pub fn resolution_rank(resolution: &str) -> i64 {
+    if let Some(c) = regex!(r"(?i)(\d{3,4})\s*[pi]").captures(resolution) {
+        return c[1].parse().unwrap_or_default();
+    }
+    if let Some(c) = regex!(r"(?i)\d{3,4}\s*[x×]\s*(\d{3,4})").captures(resolution) {
+        return c[1].parse().unwrap_or_default();
+    }
+    if regex!(r"(?i)\b4k\b").is_match(resolution) {
+        return 2160;
+    }
+    0
+}
+
+/// 修正版本，如 `- 09v2`、`[09v2]`、`[v2]`，没有时为 1
+pub fn version(title: &str) -> i64 {
+    regex!(r"(?i)(?:\d|\[|\s)v(\d)\b")
+        .captures(title)
+        .and_then(|c| c[1].parse().ok())
+        .unwrap_or(1)
+}
+
+fn parse_show(title: &str) -> Option<ShowInfo> {
+    let title = title.trim();
+    let fansub = regex!(r"^[\[【]([^\]】]+)[\]】]")
+        .captures(title)
+        .map(|c| c[1].trim().to_string())
+        .unwrap_or_default();
+    let rest = title
+        .trim_start_matches(|c| c != ']' && c != '】')
+        .trim_start_matches([']', '】']);
+    let rest = if fansub.is_empty() { title } else { rest };
+
+    // `name - 09 [...]` 或 `[name][09][...]`，`[name][01-12]` 为合集
+    let mut episode_end = None;
+    let (show, episode) = if let Some(c) =
+        regex!(r"^(.*?)[\[【\s](\d{1,4})\s*[-~]\s*(\d{1,4})(?:\s*(?:Fin|END))?[\]】\s]")
+            .captures(rest)
+    {
+        episode_end = Some(c[3].parse::<i64>().ok()?);
+        (c[1].to_string(), c[2].parse::<i64>().ok()?)
+    } else if let Some(c) = regex!(r"^(.+?)\s+-\s+(\d{1,4})(?:v\d)?\b").captures(rest) {
+        (c[1].to_string(), c[2].parse::<i64>().ok()?)
+    } else if let Some(c) = regex!(r"^(.*?)[\[【](\d{1,4})(?:v\d)?[\]】]").captures(rest) {
+        (c[1].to_string(), c[2].parse::<i64>().ok()?)
+    } else if let Some(c) = regex!(r"^(.+?)\s*第\s*(\d{1,4})\s*[话話集]").captures(rest) {
+        (c[1].to_string(), c[2].parse::<i64>().ok()?)
+    } else {
+        return None;
+    };
+    let show = clean_show(&show)?;
+
+    let season = regex!(r"(?i)\bS(\d{1,2})\b|Season\s*(\d{1,2})|第\s*(\d{1,2})\s*季")
+        .captures(title)
+        .and_then(|c| {
+            c.iter()
+                .skip(1)
+                .flatten()
+                .next()
+                .and_then(|m| m.as_str().parse().ok())
+        })
+        .unwrap_or(1);
+
+    let resolution = regex!(r"(?i)\b(\d{3,4})([pi])\b|\b(4k|2160p)\b|\b\d{3,4}x(\d{3,4})\b")
+        .captures(title)
+        .map(|c| {
+            if let (Some(n), Some(p)) = (c.get(1), c.get(2)) {
+                format!("{}{}", n.as_str(), p.as_str().to_lowercase())
+            } else if c.get(3).is_some() {
+                "4k".to_string()
+            } else {
+                match &c[4] {
+                    "2160" => "4k".to_string(),
+                    h => format!("{h}p"),
+                }
+            }
+        });
+
+    Some(ShowInfo {
+        kind: match episode_end {
+            Some(_) => ShowKind::Batch,
+            None => ShowKind::Episode,
+        },
+        fansub,
+        show,
+        season,
+        episode,
+        episode_end,
+        resolution,
+        language: language(title).to_string(),
+        year: 0,
+        tmdb_id: 0,
+    })
+}
+
+/// 去掉季度、多语言名和装饰符号
+fn clean_show(s: &str) -> Option<String> {
+    let s = regex!(r"★[^★]*★").replace_all(s, " ");
+    let brackets = regex!(r"[\[【]([^\]】]*)[\]】]");
+    let outside = brackets.replace_all(&s, " ");
+    // `[name][09]` 格式里剧名在最后一个括号中
+    let s = match outside.trim() {
+        "" => brackets
+            .captures_iter(&s)
+            .last()?
+            .get(1)?
+            .as_str()
+            .to_string(),
+        outside => outside.to_string(),
+    };
+    let s = s.split(" / ").next().unwrap_or_default();
+    let s = regex!(r"(?i)\s+(S\d{1,2}|Season\s*\d{1,2}|第\s*\d{1,2}\s*季)\s*$").replace(s, "");
+    let s = s
+        .trim()
+        .trim_matches(['[', ']', '【', '】', '-', '_'])
+        .trim();
+    (!s.is_empty()).then(|| s.to_string())
+}
+
+/// 只看括号里的标签，避免剧名里的「日常」、`GBK` 之类被误认
+fn language(title: &str) -> &'static str {
+    let words: Vec<String> = regex!(r"[\[【(（]([^\]】)）]*)[\]】)）]")
+        .captures_iter(title)
+        .flat_map(|c| {
+            c[1].split(|c: char| c.is_whitespace() || "_-&+/.,，".contains(c))
+                .filter(|w| !w.is_empty())
+                .map(str::to_uppercase)
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    // 中文标签只由这些字组成，如 `简日双语`、`繁體內嵌`
+    let is_tag = |w: &str| {
+        w.chars()
+            .all(|c| "简繁体體日中英文双雙语語内內封嵌外挂掛字幕多".contains(c))
+    };
+    let has = |tags: &[&str], chars: &[&str]| {
+        words.iter().any(|w| {
+            tags.contains(&w.as_str()) || (is_tag(w) && chars.iter().any(|c| w.contains(c)))
+        })
+    };
+    let chs = has(&["CHS", "GB", "SC", "JPSC"], &["简"]);
+    let cht = has(&["CHT", "BIG5", "TC", "JPTC"], &["繁"]);
+    let jp = has(&["JP", "JPN", "JPSC", "JPTC"], &["日"]);
+    match (chs, cht, jp) {
+        (true, true, _) => "简繁",
+        (true, false, true) => "简日",
+        (false, true, true) => "繁日",
+        (true, false, false) => "简中",
+        (false, true, false) => "繁中",
+        (false, false, _) => "生肉",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn show(title: &str) -> ShowInfo {
+        match parse(title) {
+            Recognized::Show(s) => s,
+            Recognized::Other => panic!("not recognized: {title}"),
+        }
+    }
+
+    #[test]
+    fn parse_dash_style() {
+        let s = show("[LoliHouse] 治愈魔法的错误使用方法 / Chiyu Mahou no Machigatta Tsukaikata - 09 [WebRip 1080p HEVC-10bit AAC][CHT]");
+        assert_eq!(s.fansub, "LoliHouse");
+        assert_eq!(s.show, "治愈魔法的错误使用方法");
+        assert_eq!(s.season, 1);
+        assert_eq!(s.episode, 9);
+        assert_eq!(s.resolution.as_deref(), Some("1080p"));
+        assert_eq!(s.language, "繁中");
+    }
+
+    #[test]
+    fn parse_bracket_style() {
+        let s = show("【喵萌奶茶屋】★01月新番★[秒杀外挂太强了 / Cheat Skill][07][1080p][简日双语]");
+        assert_eq!(s.fansub, "喵萌奶茶屋");
+        assert_eq!(s.show, "秒杀外挂太强了");
+        assert_eq!(s.episode, 7);
+        assert_eq!(s.language, "简日");
+    }
+
+    #[test]
+    fn parse_unknown_tags() {
+        let s = show("[GBK字幕组] 日常 - 03 [简繁内封]");
+        assert_eq!(s.show, "日常");
+        assert_eq!(s.resolution, None);
+        assert_eq!(s.language, "简繁");
+        assert_eq!(show("[Sub] 日常 - 03 [WebRip]").language, "生肉");
+        assert_eq!(show("[Sub] Show - 03 [1080p][JPSC]").language, "简日");
+    }
+
+    #[test]
+    fn parse_batch() {
+        let s = show("[Nekomoe kissaten&VCB-Studio] 孤独摇滚 / Bocchi the Rock! [01-12 Fin][Ma10p_1080p][简日]");
+        assert_eq!(s.kind, ShowKind::Batch);
+        assert_eq!(s.show, "孤独摇滚");
+        assert_eq!((s.episode, s.episode_end), (1, Some(12)));
+    }
+
+    #[test]
+    fn resolution() {
+        assert_eq!(resolution_rank("1080p"), 1080);
+        assert_eq!(resolution_rank("1920x1080"), 1080);
+        assert_eq!(resolution_rank("4K"), 2160);
+        assert!(resolution_rank("720P") < resolution_rank("2160p"));
+        assert_eq!(resolution_rank(""), 0);
+    }
+
+    #[test]
+    fn parse_version() {
+        assert_eq!(version("[LoliHouse] 某剧 - 09v2 [WebRip 1080p]"), 2);
+        assert_eq!(version("【喵萌奶茶屋】[某剧][07v3][1080p]"), 3);
+        assert_eq!(version("[ANi] 某剧 - 09 [1080P][Baha][WEB-DL]"), 1);
+        assert_eq!(version("[Sub] Overlord IV - 09 [1080p]"), 1);
+    }
+
+    #[test]
+    fn parse_other() {
+        assert!(matches!(
+            parse("[VCB-Studio] 某合集 [Ma10p_1080p]"),
+            Recognized::Other
+        ));
+    }
+}
@@ -1,14 +1,32 @@
-use super::request;
+use super::{request, AddTorrentError};
 use anyhow::{bail, Result};
-use reqwest::{multipart, Client};
-use std::{future::Future, sync::Arc};
+use reqwest::{multipart, Client, RequestBuilder, Response, StatusCode};
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// 认证方式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Auth {
+    /// 用户名密码登录，cookie 过期后自动重新登录
+    Password { username: String, password: String },
+    /// qb 5.2 起支持的 API key，以 `Authorization: Bearer` 发送
+    ApiKey(String),
+    /// qb 开启了本机或白名单免认证
+    Bypass,
+}
 
 /// 不实现 Clone，因为需要在 Drop 的时候登出
 pub struct QbClient {
     inner: Client,
     base_url: Arc<str>,
-    username: String,
-    password: String,
+    auth: Auth,
+    /// 用密码登录成功后为 true，Drop 时据此登出
+    logged_in: AtomicBool,
 }
 
 impl QbClient {
@@ -16,11 +34,7 @@ impl QbClient {
         format!("{}/api/v2/{}/{}", self.base_url, api_name, method_name)
     }
 
-    pub async fn new(
-        base_url: impl Into<Arc<str>>,
-        username: &str,
-        password: &str,
-    ) -> Result<Self> {
+    pub async fn new(base_url: impl Into<Arc<str>>, auth: Auth) -> Result<Self> {
         let mut client_builder = reqwest::ClientBuilder::new()
             .timeout(std::time::Duration::from_secs(10))
             .cookie_store(true);
@@ -29,38 +43,68 @@ impl QbClient {
             "Cache-Control",
             reqwest::header::HeaderValue::from_static("no-cache"),
         );
+        if let Auth::ApiKey(key) = &auth {
+            let mut value = reqwest::header::HeaderValue::try_from(format!("Bearer {key}"))?;
+            value.set_sensitive(true);
+            headers.insert(reqwest::header::AUTHORIZATION, value);
+        }
         client_builder = client_builder.default_headers(headers);
         let client = client_builder.build()?;
         let this = Self {
             inner: client,
             base_url: base_url.into(),
-            username: username.to_string(),
-            password: password.to_string(),
+            auth,
+            logged_in: AtomicBool::new(false),
         };
         this.login().await?;
         info!("client logged in.");
         Ok(this)
     }
 
+    /// 只有用户名密码认证需要登录
     pub async fn login(&self) -> Result<()> {
+        let Auth::Password { username, password } = &self.auth else {
+            return Ok(());
+        };
         let url = self.url("auth", "login");
         let resp = self
             .inner
             .post(&url)
-            .form(&[("username", &self.username), ("password", &self.password)])
+            .form(&[("username", username), ("password", password)])
             .send()
             .await?;
-        if resp.status().is_success() {
-            Ok(())
-        } else {
+        if !resp.status().is_success() {
             bail!("Login failed, status: {}", resp.status())
         }
+        // 密码错误时状态码也是 200，body 为 `Fails.`
+        let body = resp.text().await?;
+        if body.trim() != "Ok." {
+            bail!("Login failed: {body}")
+        }
+        self.logged_in.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// 发送请求，403 时重新登录并重试一次。`build` 会被调用两次，因为 multipart 的请求不能 clone
+    async fn send(&self, build: impl Fn(&Client) -> Result<RequestBuilder>) -> Result<Response> {
+        let response = build(&self.inner)?.send().await?;
+        if response.status() != StatusCode::FORBIDDEN || !matches!(self.auth, Auth::Password { .. })
+        {
+            return Ok(response);
+        }
+        warn!("qb session expired, logging in again");
+        self.login().await?;
+        Ok(build(&self.inner)?.send().await?)
     }
 
     pub fn logout(&self) -> impl Future<Output = Result<()>> + 'static {
+        let needs_logout = self.logged_in.swap(false, Ordering::SeqCst);
         let url = self.url("auth", "logout");
         let fut = self.inner.post(url).send();
         async move {
+            if !needs_logout {
+                return Ok(());
+            }
             let resp = fut.await?;
             if resp.status().is_success() {
                 info!("client log out success");
@@ -71,29 +115,34 @@ impl QbClient {
         }
     }
 
-    pub async fn add_torrent(&self, req: request::AddTorrentRequest) -> Result<()> {
+    /// 每次重试都要重新构造 multipart 表单
+    fn add_form(req: &request::AddTorrentRequest) -> Result<multipart::Form> {
         use multipart::Part;
         let mut form = multipart::Form::new();
         if !req.urls.is_empty() {
             form = form.part("urls", Part::text(req.urls.join("\n")));
         }
-        for torrent in req.torrents {
-            form = form.part("torrents", Part::bytes(torrent));
+        // qb 要求种子文件带文件名
+        for (i, torrent) in req.torrents.iter().enumerate() {
+            let part = Part::bytes(torrent.clone())
+                .file_name(format!("{i}.torrent"))
+                .mime_str("application/x-bittorrent")?;
+            form = form.part("torrents", part);
         }
-        if let Some(savepath) = req.savepath {
-            form = form.part("savepath", Part::text(savepath));
+        if let Some(savepath) = &req.savepath {
+            form = form.part("savepath", Part::text(savepath.clone()));
         }
-        if let Some(content_layout) = req.content_layout {
-            form = form.part("contentLayout", Part::text(content_layout));
+        if let Some(content_layout) = &req.content_layout {
+            form = form.part("contentLayout", Part::text(content_layout.clone()));
         }
-        if let Some(category) = req.category {
-            form = form.part("category", Part::text(category));
+        if let Some(category) = &req.category {
+            form = form.part("category", Part::text(category.clone()));
         }
         if !req.tags.is_empty() {
             form = form.part("tags", Part::text(req.tags.join(",")));
         }
-        if let Some(rename) = req.rename {
-            form = form.part("rename", Part::text(rename));
+        if let Some(rename) = &req.rename {
+            form = form.part("rename", Part::text(rename.clone()));
         }
         if let Some(auto_torrent_management) = req.auto_torrent_management {
             form = form.part("autoTMM", Part::text(auto_torrent_management.to_string()));
@@ -101,27 +150,295 @@ impl QbClient {
         if let Some(ratio_limit) = req.ratio_limit {
             form = form.part("ratioLimit", Part::text(ratio_limit.to_string()));
         }
+        // qb 5.0 起 paused 改名为 stopped，两个都发
+        if let Some(paused) = req.paused {
+            form = form
+                .part("paused", Part::text(paused.to_string()))
+                .part("stopped", Part::text(paused.to_string()));
+        }
+        if let Some(skip_checking) = req.skip_checking {
+            form = form.part("skip_checking", Part::text(skip_checking.to_string()));
+        }
+        if let Some(sequential) = req.sequential_download {
+            form = form.part("sequentialDownload", Part::text(sequential.to_string()));
+        }
+        if let Some(first_last) = req.first_last_piece_prio {
+            form = form.part("firstLastPiecePrio", Part::text(first_last.to_string()));
+        }
+        if let Some(up_limit) = req.up_limit {
+            form = form.part("upLimit", Part::text(up_limit.to_string()));
+        }
+        if let Some(dl_limit) = req.dl_limit {
+            form = form.part("dlLimit", Part::text(dl_limit.to_string()));
+        }
+        if let Some(seeding_time_limit) = req.seeding_time_limit {
+            form = form.part(
+                "seedingTimeLimit",
+                Part::text(seeding_time_limit.to_string()),
+            );
+        }
+        if let Some(download_path) = &req.download_path {
+            form = form
+                .part("useDownloadPath", Part::text("true"))
+                .part("downloadPath", Part::text(download_path.clone()));
+        }
+        Ok(form)
+    }
 
+    pub async fn add_torrent(&self, req: request::AddTorrentRequest) -> Result<()> {
         let url = self.url("torrents", "add");
-        let response = self.inner.post(&url).multipart(form).send().await?;
-        if response.status().is_success() {
-            info!("add torrent success");
-            if let Ok(body) = response.text().await {
-                debug!("body = {body}");
-            }
-            Ok(())
-        } else {
+        let response = self
+            .send(|c| Ok(c.post(&url).multipart(Self::add_form(&req)?)))
+            .await?;
+        if !response.status().is_success() {
             bail!("Add torrent failed, status: {}", response.status())
         }
+        // 失败时状态码也是 200，body 为 `Fails.`
+        let body = response.text().await?;
+        debug!("body = {body}");
+        if body.trim() != "Ok." {
+            return Err(AddTorrentError::Rejected { body }.into());
+        }
+        info!("add torrent success");
+        Ok(())
     }
 
-    pub async fn list_torrent(&self, tag: &str) -> Result<Vec<request::Torrent>> {
-        let url = self.url("torrents", "info");
-        let response = self.inner.post(&url).query(&[("tag", tag)]).send().await?;
+    /// 添加种子，知道 infohash 时确认种子出现在 qb 中。重复添加时视为成功
+    pub async fn add_torrent_verified(
+        &self,
+        req: request::AddTorrentRequest,
+        hash: Option<&str>,
+    ) -> Result<()> {
+        let added = self.add_torrent(req).await;
+        let Some(hash) = hash else {
+            return added;
+        };
+        if let Err(e) = added {
+            let rejected = matches!(
+                e.downcast_ref::<AddTorrentError>(),
+                Some(AddTorrentError::Rejected { .. })
+            );
+            if rejected && !self.torrents_by_hash(&[hash]).await?.is_empty() {
+                warn!("torrent {hash} already exists in qb");
+                return Ok(());
+            }
+            return Err(e);
+        }
+        self.confirm_added(hash).await
+    }
+
+    /// 轮询直到种子出现在 qb 中
+    pub async fn confirm_added(&self, hash: &str) -> Result<()> {
+        const TRIES: usize = 10;
+        for _ in 0..TRIES {
+            if !self.torrents_by_hash(&[hash]).await?.is_empty() {
+                debug!("torrent {hash} confirmed in qb");
+                return Ok(());
+            }
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        }
+        Err(AddTorrentError::NotFound {
+            hash: hash.to_string(),
+        }
+        .into())
+    }
+
+    /// 发送表单，检查状态码
+    async fn post_form<T: serde::Serialize + ?Sized>(
+        &self,
+        api_name: &str,
+        method_name: &str,
+        form: &T,
+    ) -> Result<reqwest::Response> {
+        let url = self.url(api_name, method_name);
+        let response = self.send(|c| Ok(c.post(&url).form(form))).await?;
         if !response.status().is_success() {
-            bail!("Add torrent failed, status: {}", response.status())
+            bail!(
+                "{api_name}/{method_name} failed, status: {}",
+                response.status()
+            )
         }
+        Ok(response)
+    }
+
+    pub async fn torrents(&self, filter: &request::TorrentFilter) -> Result<Vec<request::Torrent>> {
+        let response = self.post_form("torrents", "info", filter).await?;
         let torrents = response.json::<Vec<request::Torrent>>().await?;
         Ok(torrents)
     }
+
+    pub async fn torrents_by_hash(&self, hashes: &[&str]) -> Result<Vec<request::Torrent>> {
+        self.torrents(&request::TorrentFilter::hashes(hashes)).await
+    }
+
+    pub async fn list_torrent(&self, tag: &str) -> Result<Vec<request::Torrent>> {
+        self.torrents(&request::TorrentFilter::tag(tag)).await
+    }
+
+    pub async fn properties(&self, hash: &str) -> Result<request::TorrentProperties> {
+        let response = self
+            .post_form("torrents", "properties", &[("hash", hash)])
+            .await?;
+        Ok(response.json().await?)
+    }
+
+    pub async fn files(&self, hash: &str) -> Result<Vec<request::TorrentFile>> {
+        let response = self
+            .post_form("torrents", "files", &[("hash", hash)])
+            .await?;
+        Ok(response.json().await?)
+    }
+
+    /// qb 5.0 起 pause/resume 改名为 stop/start，404 时换用新接口
+    async fn post_renamed(&self, old: &str, new: &str, hashes: &[&str]) -> Result<()> {
+        let form = [("hashes", hashes.join("|"))];
+        let url = self.url("torrents", old);
+        let response = self.send(|c| Ok(c.post(&url).form(&form))).await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            self.post_form("torrents", new, &form).await?;
+        } else if !response.status().is_success() {
+            bail!("torrents/{old} failed, status: {}", response.status())
+        }
+        Ok(())
+    }
+
+    pub async fn pause(&self, hashes: &[&str]) -> Result<()> {
+        self.post_renamed("pause", "stop", hashes).await
+    }
+
+    pub async fn resume(&self, hashes: &[&str]) -> Result<()> {
+        self.post_renamed("resume", "start", hashes).await
+    }
+
+    pub async fn set_category(&self, hashes: &[&str], category: &str) -> Result<()> {
+        self.post_form(
+            "torrents",
+            "setCategory",
+            &[
+                ("hashes", hashes.join("|").as_str()),
+                ("category", category),
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn add_tags(&self, hashes: &[&str], tags: &[&str]) -> Result<()> {
+        self.post_form(
+            "torrents",
+            "addTags",
+            &[("hashes", hashes.join("|")), ("tags", tags.join(","))],
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn remove_tags(&self, hashes: &[&str], tags: &[&str]) -> Result<()> {
+        self.post_form(
+            "torrents",
+            "removeTags",
+            &[("hashes", hashes.join("|")), ("tags", tags.join(","))],
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn set_share_limits(
+        &self,
+        hashes: &[&str],
+        limits: request::ShareLimits,
+    ) -> Result<()> {
+        self.post_form(
+            "torrents",
+            "setShareLimits",
+            &[
+                ("hashes", hashes.join("|")),
+                ("ratioLimit", limits.ratio_limit.to_string()),
+                ("seedingTimeLimit", limits.seeding_time_limit.to_string()),
+                (
+                    "inactiveSeedingTimeLimit",
+                    limits.inactive_seeding_time_limit.to_string(),
+                ),
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// 修改种子在 qb 中显示的名字，不影响文件
+    pub async fn rename(&self, hash: &str, name: &str) -> Result<()> {
+        self.post_form("torrents", "rename", &[("hash", hash), ("name", name)])
+            .await?;
+        Ok(())
+    }
+
+    /// 路径相对于种子根目录
+    pub async fn rename_file(&self, hash: &str, old_path: &str, new_path: &str) -> Result<()> {
+        self.post_form(
+            "torrents",
+            "renameFile",
+            &[("hash", hash), ("oldPath", old_path), ("newPath", new_path)],
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn categories(&self) -> Result<request::Categories> {
+        let response = self.post_form("torrents", "categories", &()).await?;
+        Ok(response.json().await?)
+    }
+
+    /// 分类已存在时 qb 返回 409
+    pub async fn create_category(&self, name: &str, save_path: &str) -> Result<()> {
+        self.post_form(
+            "torrents",
+            "createCategory",
+            &[("category", name), ("savePath", save_path)],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// qb 的版本号，如 `v4.6.3`
+    pub async fn app_version(&self) -> Result<String> {
+        let url = self.url("app", "version");
+        let response = self.send(|c| Ok(c.get(&url))).await?;
+        if !response.status().is_success() {
+            bail!("app/version failed, status: {}", response.status())
+        }
+        Ok(response.text().await?.trim().to_string())
+    }
+
+    /// `delete_files` 为 true 时同时删除下载的文件
+    pub async fn delete_torrents(&self, hashes: &[&str], delete_files: bool) -> Result<()> {
+        self.post_form(
+            "torrents",
+            "delete",
+            &[
+                ("hashes", hashes.join("|")),
+                ("deleteFiles", delete_files.to_string()),
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+impl Drop for QbClient {
+    fn drop(&mut self) {
+        if !self.logged_in.load(Ordering::SeqCst) {
+            return;
+        }
+        // 运行时已经关闭时无法登出，正常退出应先调用 `Session::close`
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            warn!("no tokio runtime, qb session not logged out");
+            return;
+        };
+        let fut = self.logout();
+        handle.spawn(async move {
+            if let Err(e) = fut.await {
+                warn!("qb logout failed: {e:?}");
+            }
+        });
+    }
 }
@@ -0,0 +1,21 @@
+use std::fmt;
+
+/// 添加种子失败的原因
+#[derive(Debug)]
+pub enum AddTorrentError {
+    /// qb 返回 `Fails.`，如重复的种子或无效的链接
+    Rejected { body: String },
+    /// 添加后在 qb 中找不到
+    NotFound { hash: String },
+}
+
+impl fmt::Display for AddTorrentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rejected { body } => write!(f, "qb rejected the torrent: {body}"),
+            Self::NotFound { hash } => write!(f, "torrent {hash} not found in qb after adding"),
+        }
+    }
+}
+
+impl std::error::Error for AddTorrentError {}
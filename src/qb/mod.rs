@@ -1,4 +1,6 @@
 pub mod client;
+mod error;
 pub mod request;
 
-pub use client::QbClient;
+pub use client::{Auth, QbClient};
+pub use error::AddTorrentError;
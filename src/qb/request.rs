@@ -1,4 +1,5 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 pub struct AddTorrentRequest {
     pub urls: Vec<String>,
@@ -10,10 +11,189 @@ pub struct AddTorrentRequest {
     pub rename: Option<String>,
     pub auto_torrent_management: Option<bool>,
     pub ratio_limit: Option<f64>,
+    pub paused: Option<bool>,
+    pub skip_checking: Option<bool>,
+    pub sequential_download: Option<bool>,
+    pub first_last_piece_prio: Option<bool>,
+    /// bytes/s
+    pub up_limit: Option<u64>,
+    /// bytes/s
+    pub dl_limit: Option<u64>,
+    /// 分钟
+    pub seeding_time_limit: Option<i64>,
+    pub download_path: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+/// `torrents/info` 的过滤条件，空字段不发送
+#[derive(Debug, Default, Serialize)]
+pub struct TorrentFilter {
+    /// all, downloading, seeding, completed, paused, active, inactive, stalled, errored ...
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reverse: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<i64>,
+    /// `|` 分隔
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hashes: Option<String>,
+}
+
+impl TorrentFilter {
+    pub fn tag(tag: &str) -> Self {
+        Self {
+            tag: Some(tag.to_string()),
+            ..Default::default()
+        }
+    }
+
+    pub fn hashes(hashes: &[&str]) -> Self {
+        Self {
+            hashes: Some(hashes.join("|")),
+            ..Default::default()
+        }
+    }
+}
+
+/// 不同版本的 qb 字段不全，缺的取默认值
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
 pub struct Torrent {
+    pub hash: String,
     pub content_path: String,
     pub name: String,
+    pub save_path: String,
+    pub category: String,
+    /// `,` 分隔
+    pub tags: String,
+    /// downloading, uploading, pausedUP, stalledDL, error ...
+    pub state: String,
+    /// 0 到 1
+    pub progress: f64,
+    pub size: i64,
+    pub total_size: i64,
+    pub amount_left: i64,
+    pub downloaded: i64,
+    pub uploaded: i64,
+    pub dlspeed: i64,
+    pub upspeed: i64,
+    pub ratio: f64,
+    pub ratio_limit: f64,
+    pub seeding_time_limit: i64,
+    pub eta: i64,
+    pub num_seeds: i64,
+    pub num_leechs: i64,
+    /// unix 时间戳
+    pub added_on: i64,
+    /// 未完成时为 -1 或 0
+    pub completion_on: i64,
+    pub auto_tmm: bool,
+}
+
+impl Torrent {
+    pub fn tag_list(&self) -> Vec<&str> {
+        self.tags
+            .split(',')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TorrentProperties {
+    pub save_path: String,
+    pub creation_date: i64,
+    pub piece_size: i64,
+    pub comment: String,
+    pub total_wasted: i64,
+    pub total_uploaded: i64,
+    pub total_downloaded: i64,
+    pub up_limit: i64,
+    pub dl_limit: i64,
+    pub time_elapsed: i64,
+    pub seeding_time: i64,
+    pub nb_connections: i64,
+    pub share_ratio: f64,
+    pub addition_date: i64,
+    pub completion_date: i64,
+    pub created_by: String,
+    pub dl_speed_avg: i64,
+    pub up_speed_avg: i64,
+    pub eta: i64,
+    pub pieces_have: i64,
+    pub pieces_num: i64,
+    pub total_size: i64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TorrentFile {
+    pub index: i64,
+    /// 相对于种子根目录，`/` 分隔
+    pub name: String,
+    pub size: i64,
+    pub progress: f64,
+    /// 0 为不下载
+    pub priority: i64,
+    pub is_seed: bool,
+    pub availability: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Category {
+    pub name: String,
+    #[serde(rename = "savePath", default)]
+    pub save_path: String,
+}
+
+pub type Categories = HashMap<String, Category>;
+
+/// -2 为使用全局设置，-1 为不限制
+#[derive(Debug, Clone, Copy)]
+pub struct ShareLimits {
+    pub ratio_limit: f64,
+    /// 分钟
+    pub seeding_time_limit: i64,
+    /// 分钟，qb 4.6 起支持
+    pub inactive_seeding_time_limit: i64,
+}
+
+impl Default for ShareLimits {
+    fn default() -> Self {
+        Self {
+            ratio_limit: -2.0,
+            seeding_time_limit: -2,
+            inactive_seeding_time_limit: -2,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_torrents_info() {
+        let torrents: Vec<Torrent> =
+            serde_json::from_str(include_str!("../../tests/qb-torrents-info.json")).unwrap();
+        assert_eq!(torrents.len(), 2);
+        let t = &torrents[0];
+        assert_eq!(t.hash, "0631a44a87be60ee5a5a23ce416aabc902156320");
+        assert_eq!(t.state, "uploading");
+        assert_eq!(t.progress, 1.0);
+        assert_eq!(t.tag_list(), vec!["rss-qb", "孤独摇滚"]);
+        // 旧版本缺少的字段
+        assert_eq!(torrents[1].completion_on, 0);
+        assert!(torrents[1].tag_list().is_empty());
+    }
 }
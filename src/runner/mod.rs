@@ -1,6 +1,8 @@
 use crate::{
     config::{Email, Feed},
-    db, Config, QbClient,
+    db,
+    download::{self, Downloader},
+    Config,
 };
 use anyhow::{bail, Context, Result};
 use notify::Watcher;
@@ -12,6 +14,8 @@ use std::{
 use tokio::signal::unix as signal;
 use tokio::sync::mpsc;
 
+mod poll;
+mod reconcile;
 mod rss_;
 
 struct Reloader {
@@ -28,7 +32,11 @@ impl notify::EventHandler for Reloader {
     }
 }
 
-async fn main_loop(mut reload: mpsc::Receiver<()>, path: &Path) -> Result<()> {
+async fn main_loop(
+    mut reload: mpsc::Receiver<()>,
+    path: &Path,
+    session: &download::Session,
+) -> Result<()> {
     loop {
         tokio::select! {
             _ = reload.recv() => {
@@ -39,20 +47,20 @@ async fn main_loop(mut reload: mpsc::Receiver<()>, path: &Path) -> Result<()> {
                 info!("received signal, exiting");
                 return Ok(());
             }
-            r = run_config(path) => {
+            r = run_config(path, session) => {
                 return r;
             }
         }
     }
 }
 
-pub async fn run_watching(config_path: PathBuf) -> Result<()> {
+pub async fn run_watching(config_path: PathBuf, session: Arc<download::Session>) -> Result<()> {
     let (tx, rx) = mpsc::channel(1);
     let reloader = Reloader::new(tx);
     let mut watcher = notify::recommended_watcher(reloader)?;
     watcher.watch(&config_path, notify::RecursiveMode::NonRecursive)?;
 
-    main_loop(rx, &config_path).await
+    main_loop(rx, &config_path, &session).await
 }
 
 #[cfg(unix)]
@@ -79,7 +87,7 @@ async fn signal() -> Result<()> {
 }
 
 /// 循环跑一个 config
-async fn run_config(config_path: &Path) -> Result<()> {
+async fn run_config(config_path: &Path, session: &download::Session) -> Result<()> {
     info!("running on config {}", config_path.display());
     let config = load_config(config_path).await?;
     let config = Arc::new(config);
@@ -87,13 +95,15 @@ async fn run_config(config_path: &Path) -> Result<()> {
     let pool = db::Pool::connect(&db_url).await?;
     sqlx::migrate!().run(&pool).await?;
 
-    let qb_client = QbClient::new(
-        config.qb.base_url.clone(),
-        &config.qb.username,
-        &config.qb.password,
-    )
-    .await?;
-    let qb_client = Arc::new(qb_client);
+    // 每个下载器只登录一次，feed 之间共用
+    let mut names = vec![];
+    let mut downloaders = vec![];
+    for feed in config.feed.iter() {
+        let (name, client) = config.client(feed.base().client.as_deref())?;
+        downloaders.push(session.get(name, client).await?);
+        names.push(name);
+    }
+    session.retain(&names).await;
 
     let mut request_client_builder =
         reqwest::ClientBuilder::new().timeout(std::time::Duration::from_secs(config.timeout_s));
@@ -105,16 +115,17 @@ async fn run_config(config_path: &Path) -> Result<()> {
     let request_client = Arc::new(request_client);
 
     let mut fut = vec![];
-    for feed in config.feed.iter() {
+    for (feed, downloader) in config.feed.iter().zip(downloaders) {
         let config = config.clone();
         let feed = feed.clone();
-        let qb_client = qb_client.clone();
         let request_client = request_client.clone();
         let pool = pool.clone();
-        fut.push(async move { loop_feed(qb_client, request_client, feed, pool, &config).await });
+        fut.push(async move { loop_feed(downloader, request_client, feed, pool, &config).await });
     }
 
-    futures::future::try_join_all(fut).await?;
+    let poll = poll::loop_poll(&config, &pool, session);
+    let reconcile = reconcile::loop_reconcile(&config, &pool, session);
+    futures::future::try_join3(futures::future::try_join_all(fut), poll, reconcile).await?;
 
     Ok(())
 }
@@ -125,12 +136,13 @@ async fn load_config(path: &Path) -> Result<Config> {
         .with_context(|| format!("Cannot read config path {}", path.display()))?;
     let mut config = toml::from_str::<Config>(&config_str).context("Config file corrupted")?;
     config.update_default();
+    config.validate()?;
     Ok(config)
 }
 
 /// 循环跑一个 feed
 async fn loop_feed(
-    qb_client: Arc<QbClient>,
+    downloader: Arc<Downloader>,
     request_client: Arc<reqwest::Client>,
     feed: Feed,
     pool: db::Pool,
@@ -141,7 +153,7 @@ async fn loop_feed(
     let mut error_counter = 0;
     loop {
         timer.tick().await;
-        match run_feed_once(&qb_client, &request_client, &feed, &pool, config).await {
+        match run_feed_once(&downloader, &request_client, &feed, &pool, config).await {
             Ok(_) => {
                 info!("RSS {} 刷新完成", feed.name());
                 error_counter = 0;
@@ -170,7 +182,7 @@ async fn loop_feed(
 
 /// 跑一个 feed 并发送结果
 async fn run_feed_once(
-    qb_client: &QbClient,
+    downloader: &Downloader,
     request_client: &reqwest::Client,
     feed: &Feed,
     pool: &db::Pool,
@@ -178,20 +190,23 @@ async fn run_feed_once(
 ) -> Result<()> {
     match &config.email {
         None => {
-            run_once_inner(qb_client, request_client, feed, pool, config).await?;
+            run_once_inner(downloader, request_client, feed, pool, config).await?;
             debug!("no email configured.");
             Ok(())
         }
         Some(email) => {
-            let ret = run_once_inner(qb_client, request_client, feed, pool, config).await;
+            let ret = run_once_inner(downloader, request_client, feed, pool, config).await;
             match ret {
                 Ok(added) if !added.is_empty() => {
                     let title = format!("RSS 订阅 {} 新增 {} 个", feed.name(), added.len());
-                    let body = added
-                        .iter()
-                        .map(|item| format!("- {}", item.title))
-                        .collect::<Vec<_>>()
-                        .join("\n");
+                    let mut lines = vec![];
+                    for (item, info) in added.iter() {
+                        lines.push(format!("- {}", item.title));
+                        if let Some(url) = poster_url(info.tmdb_id, pool).await {
+                            lines.push(format!("  海报：{url}"));
+                        }
+                    }
+                    let body = lines.join("\n");
                     send(&title, &body, email).await?;
                     Ok(())
                 }
@@ -201,6 +216,20 @@ async fn run_feed_once(
     }
 }
 
+/// 通知里附上的海报，查不到时不影响发送
+async fn poster_url(tmdb_id: i64, pool: &db::Pool) -> Option<String> {
+    if tmdb_id == 0 {
+        return None;
+    }
+    match db::TmdbShow::from_id(tmdb_id, pool).await {
+        Ok(show) => show?.poster_url(),
+        Err(e) => {
+            warn!("get poster of tmdb {tmdb_id} failed: {e:#}");
+            None
+        }
+    }
+}
+
 async fn send(title: &str, body: &str, email: &Email) -> Result<()> {
     use lettre::{
         message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
@@ -225,13 +254,13 @@ async fn send(title: &str, body: &str, email: &Email) -> Result<()> {
 
 /// 跑一个 feed
 async fn run_once_inner(
-    qb_client: &QbClient,
+    downloader: &Downloader,
     request_client: &reqwest::Client,
     feed: &Feed,
     pool: &db::Pool,
     config: &Config,
-) -> Result<Vec<db::Item>> {
+) -> Result<Vec<(db::Item, crate::gpt::ShowInfo)>> {
     match feed {
-        Feed::Rss(rss) => rss.run(qb_client, request_client, pool, config).await,
+        Feed::Rss(rss) => rss.run(downloader, request_client, pool, config).await,
     }
 }
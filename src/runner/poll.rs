@@ -0,0 +1,97 @@
+use crate::{
+    config::DownloaderConfig,
+    db,
+    download::{self, DownloadClient},
+    Config,
+};
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// 定期检查已完成但还没链接的种子，代替或补充 qb 的完成回调
+pub(super) async fn loop_poll(
+    config: &Config,
+    pool: &db::Pool,
+    session: &download::Session,
+) -> Result<()> {
+    let Some(secs) = config.completion_poll_s else {
+        return Ok(());
+    };
+    let mut timer = tokio::time::interval(std::time::Duration::from_secs(secs));
+    loop {
+        timer.tick().await;
+        if let Err(e) = poll_once(config, pool, session).await {
+            warn!("poll completed torrents failed: {e:?}");
+        }
+    }
+}
+
+/// 没有 infohash 时按 qb 重命名后的种子名匹配，其他下载器不支持重命名
+pub(super) fn matches(
+    info: &db::TorrentInfo,
+    client: &DownloaderConfig,
+    torrent: &download::Torrent,
+) -> bool {
+    match &info.hash {
+        Some(hash) => *hash == torrent.hash,
+        None => {
+            matches!(client, DownloaderConfig::Qbittorrent(_))
+                && torrent.name.ends_with(&format!(" - tid{}", info.id))
+        }
+    }
+}
+
+/// 按下载器分组，每个下载器只查一次
+pub(super) fn by_client<'a>(
+    infos: &'a [db::TorrentInfo],
+    config: &'a Config,
+) -> Result<HashMap<&'a str, (&'a DownloaderConfig, Vec<&'a db::TorrentInfo>)>> {
+    let mut groups: HashMap<_, (_, Vec<_>)> = HashMap::new();
+    for info in infos {
+        let (name, client) = config.feed_client(&info.feed)?;
+        groups.entry(name).or_insert((client, vec![])).1.push(info);
+    }
+    Ok(groups)
+}
+
+async fn poll_once(config: &Config, pool: &db::Pool, session: &download::Session) -> Result<()> {
+    let active = db::TorrentInfo::active(pool).await?;
+    if active.is_empty() {
+        return Ok(());
+    }
+    // 一个下载器出错不影响其他下载器
+    for (name, (client, infos)) in by_client(&active, config)? {
+        let completed = match session.get(name, client).await {
+            Ok(downloader) => downloader.completed().await,
+            Err(e) => Err(e),
+        };
+        let completed = match completed {
+            Ok(completed) => completed,
+            Err(e) => {
+                warn!("poll client {name} failed: {e:#}");
+                continue;
+            }
+        };
+        for info in infos {
+            let Some(torrent) = completed.iter().find(|t| matches(info, client, t)) else {
+                continue;
+            };
+            // hook 可能已经在处理，只有改成 completed 的一方链接。
+            // 链接失败时 `complete` 记为 failed 并保存错误
+            let r = match db::TorrentInfo::claim_completed(info.id, pool).await {
+                Ok(true) => {
+                    info!("torrent tid{} completed, linking", info.id);
+                    crate::server::complete(info, torrent, config, pool, session).await
+                }
+                Ok(false) => {
+                    debug!("tid{} is already being handled", info.id);
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = r {
+                error!("link tid{} failed: {e:?}", info.id);
+            }
+        }
+    }
+    Ok(())
+}
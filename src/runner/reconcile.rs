@@ -0,0 +1,98 @@
+use super::poll::{by_client, matches};
+use crate::{
+    config::DownloaderConfig,
+    db::{self, TorrentState},
+    download::{self, DownloadClient},
+    Config,
+};
+use anyhow::Result;
+
+/// 刚添加的种子可能还没出现在下载器里
+const GRACE_S: i64 = 600;
+
+/// 定期对比数据库、下载器和媒体库：补上漏掉的链接，标记已从下载器删除的种子
+pub(super) async fn loop_reconcile(
+    config: &Config,
+    pool: &db::Pool,
+    session: &download::Session,
+) -> Result<()> {
+    if config.reconcile_s == 0 {
+        return Ok(());
+    }
+    let mut timer = tokio::time::interval(std::time::Duration::from_secs(config.reconcile_s));
+    loop {
+        timer.tick().await;
+        if let Err(e) = reconcile_once(config, pool, session).await {
+            warn!("reconcile torrents failed: {e:?}");
+        }
+    }
+}
+
+async fn reconcile_once(
+    config: &Config,
+    pool: &db::Pool,
+    session: &download::Session,
+) -> Result<()> {
+    let tracked = db::TorrentInfo::tracked(pool).await?;
+    let now = chrono::Utc::now().timestamp();
+    let (mut relinked, mut removed) = (0, 0);
+    for (name, (client, infos)) in by_client(&tracked, config)? {
+        // 一个下载器出错不影响其他下载器
+        let downloader = match session.get(name, client).await {
+            Ok(downloader) => downloader,
+            Err(e) => {
+                warn!("reconcile client {name} failed: {e:#}");
+                continue;
+            }
+        };
+        let torrents = match downloader.list().await {
+            Ok(torrents) => torrents,
+            Err(e) => {
+                warn!("reconcile client {name} failed: {e:#}");
+                continue;
+            }
+        };
+        for info in infos {
+            let state = info.state()?;
+            // 被替换的旧版本不再链接
+            if state == TorrentState::Replaced {
+                continue;
+            }
+            let Some(torrent) = torrents.iter().find(|t| matches(info, client, t)) else {
+                // 失败的种子可能从没进过下载器，保留失败状态。
+                // 没有 infohash 时只有 qb 能按名字找到，其他下载器不能确定已删除
+                let unknown =
+                    info.hash.is_none() && !matches!(client, DownloaderConfig::Qbittorrent(_));
+                if unknown {
+                    debug!("tid{} has no infohash, skip", info.id);
+                } else if state != TorrentState::Failed && now - info.updated_at > GRACE_S {
+                    info!("tid{} is no longer in {}", info.id, downloader.name());
+                    db::TorrentInfo::set_state(info.id, TorrentState::Removed, None, pool).await?;
+                    removed += 1;
+                }
+                continue;
+            };
+            if !torrent.done {
+                if state == TorrentState::Added {
+                    db::TorrentInfo::set_state(info.id, TorrentState::Downloading, None, pool)
+                        .await?;
+                }
+                continue;
+            }
+            // 已链接的检查媒体库里的文件是否还在
+            if state == TorrentState::Linked && crate::server::link_exists(info, config)? {
+                continue;
+            }
+            info!("tid{} is {state:?} but not linked, linking", info.id);
+            match crate::server::complete(info, torrent, config, pool, session).await {
+                Ok(()) => relinked += 1,
+                Err(e) => error!("link tid{} failed: {e:?}", info.id),
+            }
+        }
+    }
+    info!(
+        "reconciled {} torrents: {relinked} linked, {removed} removed",
+        tracked.len()
+    );
+    Ok(())
+}
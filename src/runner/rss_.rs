@@ -1,23 +1,26 @@
-use std::collections::HashSet;
+use chrono::Datelike;
+use std::collections::{HashMap, HashSet};
 
-use crate::config::RssFeed;
+use crate::config::{Dedup, Naming, OverBudget, RssFeed, Upgrade};
 use crate::db;
+use crate::download::{DownloadClient, Downloader};
 use crate::gpt;
-use crate::tmdb;
-use crate::QbClient;
+use crate::metadata;
+use crate::series::{self, Decision};
+use crate::torrent;
 use anyhow::bail;
 use anyhow::{Context, Result};
 use url::Url;
 
 impl RssFeed {
-    /// 返回成功的 item
+    /// 返回成功的 item 及其剧集信息
     pub async fn run(
         &self,
-        qb_client: &QbClient,
+        downloader: &Downloader,
         request_client: &reqwest::Client,
         pool: &db::Pool,
         config: &crate::Config,
-    ) -> Result<Vec<db::Item>> {
+    ) -> Result<Vec<(db::Item, gpt::ShowInfo)>> {
         info!("Fetching feed {}", self.name);
         let items = self
             .get_items(request_client)
@@ -34,95 +37,383 @@ impl RssFeed {
             filtered_items.push(item);
         }
         let items = filtered_items;
+        let items = if items.is_empty() {
+            vec![]
+        } else {
+            self.recognize(items, request_client, pool, config).await?
+        };
+        let mut accepted = vec![];
+        // 检查规则时下载的种子文件，添加时不再下载
+        let mut fetched = HashMap::new();
+        for (item, info) in items {
+            if !self.base.accepts(&info) {
+                info!("item {} filtered out by show filter", item.title);
+                item.insert(pool).await?;
+                continue;
+            }
+            if self.base.checks_torrent() && !item.enclosure.starts_with("magnet:") {
+                // 单个种子下载失败不影响其他的，下次刷新时重试
+                let bytes = match torrent::fetch(
+                    request_client,
+                    &item.enclosure,
+                    self.base.cookie.as_deref(),
+                )
+                .await
+                {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        warn!("skip {}: {e:#}", item.title);
+                        continue;
+                    }
+                };
+                let ok = match torrent::Metainfo::parse(&bytes) {
+                    Ok(meta) => self.base.check_torrent(&meta),
+                    Err(e) => {
+                        warn!("invalid torrent {}: {e:#}", item.title);
+                        false
+                    }
+                };
+                if !ok {
+                    info!("item {} filtered out by torrent rules", item.title);
+                    item.insert(pool).await?;
+                    continue;
+                }
+                fetched.insert(item.guid.clone(), bytes);
+            }
+            accepted.push((item, info));
+        }
+        let items = accepted;
+        let mut items = match self.base.delay_s.filter(|&d| d > 0) {
+            Some(delay) => self.hold(items, delay, pool).await?,
+            None => items.into_iter().map(|(i, s)| (i, s, None)).collect(),
+        };
         if items.is_empty() {
             return Ok(vec![]);
         }
 
-        // GPT 提取并过滤剧集信息
-        let titles = items.iter().map(|s| s.title.clone()).collect::<Vec<_>>();
-        let info = crate::gpt::get_episode_info(&titles, &config.gpt).await?;
-        let mut items = items
-            .into_iter()
-            .zip(info)
-            .filter_map(|(item, info)| {
-                let gpt::Recognized::Show(info) = info else {
-                    return None;
-                };
-                Some((item, info))
-            })
-            .collect::<Vec<_>>();
-
-        // 用 tmdb 获取正确的剧名
-        let titles = items
-            .iter()
-            .map(|(_, info)| info.show.clone())
-            .collect::<HashSet<_>>();
-        debug!("query tmdb for titles: {titles:?}");
-        let mapper =
-            tmdb::get_info(request_client.clone(), &config.tmdb_secret, titles, pool).await?;
-        debug!("tmdb map: {mapper:#?}");
-        for item in items.iter_mut() {
-            if let Some(tmdb) = mapper.get(&item.1.show) {
-                item.1.show = tmdb.tmdb_name.clone();
-                item.1.year = tmdb.year;
-                item.1.tmdb_id = tmdb.tmdb_id;
-            }
+        let dedup = self.base.dedup();
+        let upgrade = self.base.upgrade();
+        if dedup == Dedup::Best || upgrade == Upgrade::Better {
+            // 同一批里有同一集时，先下载最好的
+            items.sort_by_cached_key(|(item, info, _)| {
+                std::cmp::Reverse(self.base.score(info, &item.title))
+            });
         }
 
         let mut answer = vec![];
-        qb_client.login().await?;
-        for (item, info) in items {
+        for (item, info, pending) in items {
+            let quality = self.base.score(&info, &item.title);
+            let episode = info.series_episode(&item, &self.name, quality);
+            let mut replaces = None;
+            if let Some(ep) = episode
+                .as_ref()
+                .filter(|_| dedup != Dedup::Allow || upgrade != Upgrade::Never)
+            {
+                let downloaded = ep.downloaded(pool).await?;
+                match series::decide(ep, &downloaded, dedup, upgrade) {
+                    Decision::Download => {}
+                    Decision::Skip => {
+                        info!(
+                            "feed {} skipped {}: same episode already downloaded",
+                            self.name, item.title
+                        );
+                        self.finish(&item, pending, pool).await?;
+                        continue;
+                    }
+                    Decision::Replace(old) => {
+                        info!("feed {} upgrades tid{old} to {}", self.name, item.title);
+                        replaces = Some(old);
+                    }
+                }
+            }
             info!("series {} new episode {info:?}", self.name);
 
             // insert into db
             let torrent_id = db::TorrentInfo::gen_id();
-            db::TorrentInfo {
+            let torrent_info = db::TorrentInfo {
                 id: torrent_id,
                 name: info.show.clone(),
                 year: info.year,
                 season: info.season,
                 episode: info.episode,
                 fansub: info.fansub.clone(),
-                resolution: info.resolution.clone(),
+                resolution: info.resolution.clone().unwrap_or_default(),
                 language: info.language.clone(),
                 tmdb_id: info.tmdb_id,
+                kind: info.kind.as_str().to_string(),
+                episode_end: info.episode_end,
+                feed: self.name.clone(),
+                replaces,
+                hash: None,
+                state: db::TorrentState::Added.as_str().to_string(),
+                error: None,
+                updated_at: chrono::Utc::now().timestamp(),
+            };
+            let rename = format!(
+                "{anime} - {label} - {resolution} - {language} - {fansub} - tid{torrent_id}",
+                anime = info.show,
+                label = torrent_info.episode_label(),
+                resolution = info.resolution.as_deref().unwrap_or("unknown"),
+                language = info.language,
+                fansub = info.fansub
+            );
+            torrent_info.insert(pool).await?;
+            // 添加失败时撤销
+            let mut tx = db::EpTransaction::new(pool.clone());
+            if let Some(mut ep) = episode {
+                ep.torrent_id = torrent_id;
+                ep.insert(&mut tx).await?;
             }
-            .insert(pool)
-            .await?;
 
+            // 不上传时也下载种子文件，用 infohash 确认下载器添加成功
+            let bytes = match fetched.remove(&item.guid) {
+                Some(bytes) => Some(bytes),
+                None if item.enclosure.starts_with("magnet:") => None,
+                None => {
+                    match torrent::fetch(
+                        request_client,
+                        &item.enclosure,
+                        self.base.cookie.as_deref(),
+                    )
+                    .await
+                    {
+                        Ok(bytes) => Some(bytes),
+                        Err(e) if !self.base.upload_torrent() => {
+                            warn!("fetch torrent {} failed: {e:#}", item.title);
+                            None
+                        }
+                        Err(e) => {
+                            add_failed(torrent_id, &item, e, pool).await?;
+                            continue;
+                        }
+                    }
+                }
+            };
+            // 种子文件下载失败时这里不知道 infohash，由下载器返回
+            let hash = match &bytes {
+                Some(bytes) => torrent::Metainfo::parse(bytes)
+                    .map(|m| m.info_hash)
+                    .map_err(|e| warn!("invalid torrent {}: {e:#}", item.title))
+                    .ok(),
+                None => torrent::magnet_hash(&item.enclosure),
+            };
+            // 磁力链接只能交给下载器
+            let (urls, torrents) = match bytes {
+                Some(bytes) if self.base.upload_torrent() => (vec![], vec![bytes]),
+                _ => (vec![item.enclosure.clone()], vec![]),
+            };
             let mut tags = self.base.tags.clone().unwrap_or_default();
             tags.push(info.show.clone());
-            qb_client
-                .add_torrent(crate::request::AddTorrentRequest {
-                    urls: vec![item.enclosure.clone()],
-                    torrents: vec![],
-                    savepath: self.base.savepath.clone(),
-                    content_layout: self.base.content_layout.map(|i| i.to_string()),
-                    category: self.base.category.clone(),
-                    tags,
-                    rename: Some(format!(
-                        "{anime} - S{season:02}E{ep:02} - {resolution} - {language} - {fansub} - tid{torrent_id}",
-                        anime = info.show,
-                        season = info.season,
-                        ep = info.episode,
-                        resolution = info.resolution,
-                        language = info.language,
-                        fansub = info.fansub
-                    )),
-                    auto_torrent_management: self.base.auto_torrent_management,
-                    ratio_limit: self.base.ratio_limit,
-                })
+            let added = match downloader
+                .add(
+                    crate::request::AddTorrentRequest {
+                        urls,
+                        torrents,
+                        savepath: self.base.savepath.clone(),
+                        content_layout: self.base.content_layout.map(|i| i.to_string()),
+                        category: self.base.category.clone(),
+                        tags,
+                        rename: Some(rename),
+                        auto_torrent_management: self.base.auto_torrent_management,
+                        ratio_limit: self.base.ratio_limit,
+                        paused: self.base.paused,
+                        skip_checking: self.base.skip_checking,
+                        sequential_download: self.base.sequential_download,
+                        first_last_piece_prio: self.base.first_last_piece_prio,
+                        up_limit: self.base.up_limit_kb.map(|kb| kb * 1024),
+                        dl_limit: self.base.dl_limit_kb.map(|kb| kb * 1024),
+                        seeding_time_limit: self.base.seeding_time_limit_min,
+                        download_path: self.base.download_path.clone(),
+                    },
+                    hash.as_deref(),
+                )
                 .await
-                .context("add torrent failed")?;
-            info!("种子 {} {info:?} 成功添加到 QB", item.title);
+            {
+                Ok(added) => added,
+                Err(e) => {
+                    add_failed(torrent_id, &item, e, pool).await?;
+                    continue;
+                }
+            };
+            info!(
+                "种子 {} {info:?} 成功添加到 {}",
+                item.title,
+                downloader.name()
+            );
+            if let Some(hash) = added {
+                db::TorrentInfo::set_hash(torrent_id, &hash, pool).await?;
+            }
 
-            item.insert(pool).await?;
-            answer.push(item);
+            self.finish(&item, pending, pool).await?;
+            tx.commit();
+            answer.push((item, info));
         }
 
         Ok(answer)
     }
 
+    /// GPT 提取剧集信息，再用元数据修正剧名、季和集
+    async fn recognize(
+        &self,
+        items: Vec<db::Item>,
+        request_client: &reqwest::Client,
+        pool: &db::Pool,
+        config: &crate::Config,
+    ) -> Result<Vec<(db::Item, gpt::ShowInfo)>> {
+        // GPT 提取并过滤剧集信息
+        if config.gpt.over_budget == OverBudget::Pause
+            && gpt::budget_exceeded(&config.gpt, pool).await?
+        {
+            warn!("gpt monthly budget exceeded, feed {} paused", self.name);
+            return Ok(vec![]);
+        }
+        let titles = items.iter().map(|s| s.title.clone()).collect::<Vec<_>>();
+        let info = gpt::get_episode_info(&titles, &config.gpt, &self.name, pool).await?;
+        let mut shows = vec![];
+        for (item, info) in items.into_iter().zip(info) {
+            // 不是剧集的也记录下来，不再重复识别
+            let gpt::Recognized::Show(mut info) = info else {
+                debug!("item {} is not a show, skip", item.title);
+                item.insert(pool).await?;
+                continue;
+            };
+            if info.kind == gpt::ShowKind::Special {
+                info.season = 0;
+            }
+            shows.push((item, info));
+        }
+        let mut items = shows;
+
+        // 用 tmdb 获取正确的剧名
+        let mut titles = HashMap::new();
+        for (item, info) in items.iter() {
+            let release_year = item
+                .pub_date
+                .and_then(|t| chrono::DateTime::from_timestamp(t, 0))
+                .unwrap_or_else(chrono::Utc::now)
+                .year();
+            titles
+                .entry((info.show.clone(), info.kind.into()))
+                .and_modify(|h: &mut metadata::Hint| {
+                    h.release_year = h.release_year.min(release_year)
+                })
+                .or_insert(metadata::Hint {
+                    release_year,
+                    anime: self.base.anime(),
+                });
+        }
+        debug!("query tmdb for titles: {titles:?}");
+        let locale = self.base.locale();
+        let mapper =
+            metadata::get_info(request_client.clone(), config, &locale, titles, pool).await?;
+        debug!("tmdb map: {mapper:#?}");
+        // 查询出错的不记录，下次刷新时重试，避免用识别出的剧名建错目录
+        items.retain(|(item, info)| {
+            let found = mapper.contains_key(&(info.show.clone(), info.kind.into()));
+            if !found {
+                warn!("metadata lookup failed, retry {} later", item.title);
+            }
+            found
+        });
+        let tmdb_ids = items
+            .iter()
+            .filter(|(_, info)| info.kind == gpt::ShowKind::Episode)
+            .filter_map(|(_, info)| mapper.get(&(info.show.clone(), info.kind.into()))?.as_ref())
+            .map(|tmdb| tmdb.tmdb_id)
+            .collect::<HashSet<_>>();
+        let seasons =
+            metadata::get_seasons(request_client.clone(), &config.tmdb_secret, tmdb_ids, pool)
+                .await?;
+        for (_, info) in items.iter_mut() {
+            let raw_name = info.show.clone();
+            if let Some(Some(tmdb)) = mapper.get(&(raw_name.clone(), info.kind.into())) {
+                info.show = match self.base.naming() {
+                    Naming::Original if !tmdb.original_name.is_empty() => {
+                        tmdb.original_name.clone()
+                    }
+                    _ => tmdb.tmdb_name.clone(),
+                };
+                info.year = tmdb.year;
+                info.tmdb_id = tmdb.tmdb_id;
+                if tmdb.tmdb_id == 0 {
+                    debug!("{raw_name} has no tmdb id, dedup by name and skip episode remap");
+                }
+            }
+            if info.kind != gpt::ShowKind::Episode {
+                continue;
+            }
+            // 手动修正优先于自动换算
+            if let Some(o) = config.episode_override(&[&raw_name, &info.show]) {
+                (info.season, info.episode) = o.apply(info.season, info.episode);
+                debug!(
+                    "show {raw_name} overridden to S{}E{}",
+                    info.season, info.episode
+                );
+            } else if let Some((season, episode)) = seasons
+                .get(&info.tmdb_id)
+                .and_then(|s| metadata::remap_absolute(s, info.season, info.episode))
+            {
+                info!(
+                    "remap {raw_name} S{}E{} => S{season}E{episode}",
+                    info.season, info.episode
+                );
+                info.season = season;
+                info.episode = episode;
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// 新版本放入等待期，返回等待期已过的集中得分最高的版本
+    async fn hold(
+        &self,
+        items: Vec<(db::Item, gpt::ShowInfo)>,
+        delay: u64,
+        pool: &db::Pool,
+    ) -> Result<Vec<(db::Item, gpt::ShowInfo, Option<db::Pending>)>> {
+        for (item, info) in items {
+            let score = self.base.score(&info, &item.title);
+            info!("feed {} holds {} for {delay}s", self.name, item.title);
+            info.pending(&item, &self.name, score).insert(pool).await?;
+            item.insert(pool).await?;
+        }
+        let before = chrono::Utc::now().timestamp() - delay as i64;
+        let mut ans: Vec<(db::Item, gpt::ShowInfo, Option<db::Pending>)> = vec![];
+        // 已按得分排好序，每集取第一个
+        for p in db::Pending::due(&self.name, before, pool).await? {
+            if ans
+                .iter()
+                .any(|(_, _, best)| best.as_ref().is_some_and(|b| b.same_episode(&p)))
+            {
+                debug!(
+                    "feed {} drops {}: a better version exists",
+                    self.name, p.title
+                );
+                continue;
+            }
+            let (item, info) = p.to_show()?;
+            ans.push((item, info, Some(p)));
+        }
+        Ok(ans)
+    }
+
+    /// 标记处理完成，等待期的版本同时删除同一集的其他候选
+    async fn finish(
+        &self,
+        item: &db::Item,
+        pending: Option<db::Pending>,
+        pool: &db::Pool,
+    ) -> Result<()> {
+        match pending {
+            Some(p) => {
+                p.remove_group(pool).await?;
+            }
+            None => item.insert(pool).await?,
+        }
+        Ok(())
+    }
+
     async fn get_items(&self, client: &reqwest::Client) -> Result<Vec<db::Item>> {
         let url = self.url();
         let r = client
@@ -178,6 +469,18 @@ impl RssFeed {
     }
 }
 
+/// 一个种子添加失败不影响其他的。不记录 item，下次刷新时重试
+async fn add_failed(
+    torrent_id: i64,
+    item: &db::Item,
+    e: anyhow::Error,
+    pool: &db::Pool,
+) -> Result<()> {
+    error!("add torrent {} failed: {e:#}", item.title);
+    let error = format!("add failed: {e:#}");
+    db::TorrentInfo::set_state(torrent_id, db::TorrentState::Failed, Some(&error), pool).await
+}
+
 impl TryFrom<rss::Item> for db::Item {
     type Error = anyhow::Error;
     fn try_from(value: rss::Item) -> Result<Self, Self::Error> {
@@ -188,6 +491,10 @@ impl TryFrom<rss::Item> for db::Item {
             title: value.title.unwrap_or_else(|| "unknown".to_string()),
             link: value.link.unwrap_or_else(|| "unknown".to_string()),
             enclosure,
+            pub_date: value
+                .pub_date
+                .and_then(|d| chrono::DateTime::parse_from_rfc2822(&d).ok())
+                .map(|d| d.timestamp()),
         })
     }
 }
@@ -1,4 +1,6 @@
+use crate::config::{Dedup, Upgrade};
 use crate::db;
+use crate::gpt::{ShowInfo, ShowKind};
 use regex::Regex;
 
 impl db::Item {
@@ -10,3 +12,227 @@ impl db::Item {
         Some((season, ep))
     }
 }
+
+impl ShowInfo {
+    /// 去重用的记录，合集不去重。`torrent_id` 在添加时填写
+    pub fn series_episode(
+        &self,
+        item: &db::Item,
+        feed: &str,
+        quality: i64,
+    ) -> Option<db::SeriesEpisode> {
+        if self.kind == ShowKind::Batch {
+            return None;
+        }
+        Some(db::SeriesEpisode {
+            series_name: self.show.clone(),
+            tmdb_id: self.tmdb_id,
+            series_season: self.season,
+            series_episode: self.episode,
+            item_guid: item.guid.clone(),
+            feed: feed.to_string(),
+            quality,
+            torrent_id: 0,
+            fansub: self.fansub.clone(),
+            version: crate::parser::version(&item.title),
+        })
+    }
+
+    /// 放入等待期，`created_at` 为当前时间
+    pub fn pending(&self, item: &db::Item, feed: &str, score: i64) -> db::Pending {
+        db::Pending {
+            id: 0,
+            feed: feed.to_string(),
+            guid: item.guid.clone(),
+            title: item.title.clone(),
+            link: item.link.clone(),
+            enclosure: item.enclosure.clone(),
+            show: self.show.clone(),
+            year: self.year,
+            tmdb_id: self.tmdb_id,
+            kind: self.kind.as_str().to_string(),
+            season: self.season,
+            episode: self.episode,
+            episode_end: self.episode_end,
+            fansub: self.fansub.clone(),
+            resolution: self.resolution.clone().unwrap_or_default(),
+            language: self.language.clone(),
+            score,
+            created_at: chrono::Utc::now().timestamp(),
+        }
+    }
+}
+
+impl db::Pending {
+    pub fn to_show(&self) -> anyhow::Result<(db::Item, ShowInfo)> {
+        let item = db::Item {
+            guid: self.guid.clone(),
+            title: self.title.clone(),
+            link: self.link.clone(),
+            enclosure: self.enclosure.clone(),
+            pub_date: None,
+        };
+        let info = ShowInfo {
+            kind: self.kind.parse()?,
+            fansub: self.fansub.clone(),
+            show: self.show.clone(),
+            season: self.season,
+            episode: self.episode,
+            episode_end: self.episode_end,
+            resolution: Some(self.resolution.clone()).filter(|r| !r.is_empty()),
+            language: self.language.clone(),
+            year: self.year,
+            tmdb_id: self.tmdb_id,
+        };
+        Ok((item, info))
+    }
+}
+
+/// 同一集已经下载过时的处理
+#[derive(Debug, PartialEq, Eq)]
+pub enum Decision {
+    Download,
+    Skip,
+    /// 下载并替换这个旧种子
+    Replace(i64),
+}
+
+/// `downloaded` 为同一集已下载的版本，按下载顺序
+pub fn decide(
+    new: &db::SeriesEpisode,
+    downloaded: &[db::SeriesEpisode],
+    dedup: Dedup,
+    upgrade: Upgrade,
+) -> Decision {
+    if downloaded.is_empty() {
+        return Decision::Download;
+    }
+    let best = downloaded
+        .iter()
+        .map(|e| e.quality)
+        .max()
+        .unwrap_or_default();
+    // 同字幕组下载过这一集，并且都是旧版本
+    let mut same_fansub = downloaded
+        .iter()
+        .filter(|e| e.fansub == new.fansub)
+        .peekable();
+    let newer_version =
+        same_fansub.peek().is_some() && same_fansub.all(|e| new.version > e.version);
+    let better = new.quality > best;
+    // 替换同一集里排名最差的版本，新版本只替换同字幕组的
+    let worst = |same_fansub: bool| {
+        downloaded
+            .iter()
+            .filter(|e| {
+                e.series_season == new.series_season && e.series_episode == new.series_episode
+            })
+            .filter(|e| !same_fansub || e.fansub == new.fansub)
+            .min_by_key(|e| (e.quality, e.version))
+            .map(|e| Decision::Replace(e.torrent_id))
+    };
+    let replace = match upgrade {
+        Upgrade::Version | Upgrade::Better if newer_version => worst(true),
+        Upgrade::Better if better => worst(false),
+        _ => None,
+    };
+    if let Some(replace) = replace {
+        return replace;
+    }
+    match dedup {
+        Dedup::Allow => Decision::Download,
+        Dedup::First => Decision::Skip,
+        Dedup::Best if better => Decision::Download,
+        Dedup::Best => Decision::Skip,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ep(torrent_id: i64, fansub: &str, quality: i64, version: i64) -> db::SeriesEpisode {
+        db::SeriesEpisode {
+            series_name: "某剧".to_string(),
+            tmdb_id: 1,
+            series_season: 1,
+            series_episode: 1,
+            item_guid: torrent_id.to_string(),
+            feed: "feed".to_string(),
+            quality,
+            torrent_id,
+            fansub: fansub.to_string(),
+            version,
+        }
+    }
+
+    #[test]
+    fn decide_upgrade() {
+        let old = [ep(1, "ANi", 1080, 1)];
+        let v2 = ep(0, "ANi", 1080, 2);
+        let better = ep(0, "LoliHouse", 2160, 1);
+        let worse = ep(0, "LoliHouse", 720, 1);
+
+        assert_eq!(
+            decide(&v2, &[], Dedup::First, Upgrade::Never),
+            Decision::Download
+        );
+        assert_eq!(
+            decide(&v2, &old, Dedup::First, Upgrade::Never),
+            Decision::Skip
+        );
+        assert_eq!(
+            decide(&v2, &old, Dedup::First, Upgrade::Version),
+            Decision::Replace(1)
+        );
+        assert_eq!(
+            decide(&better, &old, Dedup::First, Upgrade::Version),
+            Decision::Skip
+        );
+        assert_eq!(
+            decide(&better, &old, Dedup::First, Upgrade::Better),
+            Decision::Replace(1)
+        );
+        assert_eq!(
+            decide(&worse, &old, Dedup::Best, Upgrade::Better),
+            Decision::Skip
+        );
+        assert_eq!(
+            decide(&worse, &old, Dedup::Allow, Upgrade::Better),
+            Decision::Download
+        );
+
+        // 替换最差的版本，而不是最后下载的
+        let old = [ep(1, "ANi", 720, 1), ep(2, "LoliHouse", 1080, 1)];
+        assert_eq!(
+            decide(&better, &old, Dedup::Allow, Upgrade::Better),
+            Decision::Replace(1)
+        );
+        let v2 = ep(0, "LoliHouse", 1080, 2);
+        assert_eq!(
+            decide(&v2, &old, Dedup::Allow, Upgrade::Version),
+            Decision::Replace(2)
+        );
+
+        // 最后下载的是其他字幕组时，同字幕组的新版本照样替换
+        let old = [
+            ep(1, "ANi", 1080, 1),
+            ep(2, "LoliHouse", 1080, 1),
+            ep(3, "ANi", 720, 1),
+        ];
+        assert_eq!(
+            decide(&v2, &old, Dedup::First, Upgrade::Version),
+            Decision::Replace(2)
+        );
+        let v2 = ep(0, "ANi", 1080, 2);
+        assert_eq!(
+            decide(&v2, &old, Dedup::First, Upgrade::Version),
+            Decision::Replace(3)
+        );
+        let new = ep(0, "Other", 1080, 2);
+        assert_eq!(
+            decide(&new, &old, Dedup::First, Upgrade::Version),
+            Decision::Skip
+        );
+    }
+}
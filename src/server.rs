@@ -1,93 +1,388 @@
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use actix_web::{post, App, HttpResponse, HttpServer, Responder};
+use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder};
 use anyhow::{bail, Context, Result};
 
-use crate::{db, Config};
+use crate::{
+    db,
+    download::{self, DownloadClient, Downloader},
+    gpt::{Recognized, ShowKind},
+    Config,
+};
 
-async fn on_hook(body: bytes::Bytes) -> Result<()> {
-    info!("running hook");
-    let task_title = std::str::from_utf8(&body).context("Invalid body")?;
-    debug!("hook: task_title = {task_title}");
-    let tid = task_title
-        .rsplit(" - tid")
-        .next()
-        .context("No tid")?
-        .parse::<i64>()
-        .context("Invalid tid")?;
-
-    // load config
+async fn load() -> Result<(Config, db::Pool)> {
     let path = "config.toml".parse::<std::path::PathBuf>()?;
     let config = tokio::fs::read_to_string(path).await?;
     let mut config = toml::from_str::<Config>(&config).context("Invalid toml config")?;
     config.update_default();
+    config.validate()?;
     debug!("config loaded");
 
-    // load from tid
     let db_url = format!("sqlite://{}", config.db_uri.display());
     let pool = db::Pool::connect(&db_url).await?;
-    let torrent_info = db::TorrentInfo::from_id(tid, &pool).await?;
-    debug!("torrent info loaded from db: {torrent_info:?}");
+    Ok((config, pool))
+}
 
-    // load from qbittorrent
-    let qb_client = crate::QbClient::new(
-        config.qb.base_url.clone(),
-        &config.qb.username,
-        &config.qb.password,
-    )
-    .await?;
-    qb_client.login().await?;
-    let torrents = qb_client.list_torrent(&torrent_info.name).await?;
-    let torrent = torrents
+/// 有 infohash 时按 infohash 找，否则按 qb 里带 tid 的种子名找
+async fn find_torrent(
+    downloader: &Downloader,
+    info: &db::TorrentInfo,
+) -> Result<Option<download::Torrent>> {
+    if let Some(hash) = &info.hash {
+        return Ok(downloader.get(&[hash]).await?.into_iter().next());
+    }
+    let suffix = format!(" - tid{}", info.id);
+    Ok(downloader
+        .list()
+        .await?
         .into_iter()
-        .find(|t| t.name == task_title)
+        .find(|t| t.name.ends_with(&suffix)))
+}
+
+/// body 为 qb 中带 tid 的种子名，其他下载器可以发送 infohash
+async fn on_hook(body: bytes::Bytes, session: &download::Session) -> Result<()> {
+    info!("running hook");
+    let task_title = std::str::from_utf8(&body).context("Invalid body")?.trim();
+    debug!("hook: task_title = {task_title}");
+
+    let (config, pool) = load().await?;
+
+    // load from tid or hash
+    let torrent_info = match task_title.rsplit_once(" - tid") {
+        Some((_, tid)) => {
+            let tid = tid.parse::<i64>().context("Invalid tid")?;
+            db::TorrentInfo::from_id(tid, &pool).await?
+        }
+        None => db::TorrentInfo::from_hash(&task_title.to_lowercase(), &pool)
+            .await
+            .context("No tid or known hash")?,
+    };
+    debug!("torrent info loaded from db: {torrent_info:?}");
+
+    // load from downloader
+    let (name, client) = config.feed_client(&torrent_info.feed)?;
+    let downloader = session.get(name, client).await?;
+    let torrent = find_torrent(&downloader, &torrent_info)
+        .await?
         .context("torrent not found")?;
-    debug!("torrent loaded from qb: {torrent:?}");
-    drop(qb_client);
+    debug!("torrent loaded from {}: {torrent:?}", downloader.name());
+
+    // 轮询可能已经在处理
+    if !db::TorrentInfo::claim_completed(torrent_info.id, &pool).await? {
+        info!("tid{} is already handled, skip", torrent_info.id);
+        return Ok(());
+    }
+    complete(&torrent_info, &torrent, &config, &pool, session).await
+}
+
+/// 下载完成后链接到媒体库，可以重复执行，结果记录在种子状态里。hook、轮询和对账共用
+pub(crate) async fn complete(
+    torrent_info: &db::TorrentInfo,
+    torrent: &download::Torrent,
+    config: &Config,
+    pool: &db::Pool,
+    session: &download::Session,
+) -> Result<()> {
+    let r = link_completed(torrent_info, torrent, config, pool, session).await;
+    match &r {
+        Ok(()) => {
+            db::TorrentInfo::set_state(torrent_info.id, db::TorrentState::Linked, None, pool)
+                .await?
+        }
+        Err(e) => {
+            let error = format!("link failed: {e:#}");
+            db::TorrentInfo::set_state(
+                torrent_info.id,
+                db::TorrentState::Failed,
+                Some(&error),
+                pool,
+            )
+            .await?
+        }
+    }
+    r
+}
+
+async fn link_completed(
+    torrent_info: &db::TorrentInfo,
+    torrent: &download::Torrent,
+    config: &Config,
+    pool: &db::Pool,
+    session: &download::Session,
+) -> Result<()> {
+    let replaced = match torrent_info.replaces {
+        Some(old) => match db::TorrentInfo::from_id(old, pool).await {
+            Ok(old) => Some(old),
+            Err(e) => {
+                warn!("replaced torrent tid{old} not found: {e:#}");
+                None
+            }
+        },
+        None => None,
+    };
+
+    let old_links = match &replaced {
+        Some(old) if old.kind != ShowKind::Batch.as_str() => linked_files(old, config)?,
+        _ => vec![],
+    };
 
     // link against
     let src = torrent.content_path.parse::<PathBuf>()?;
     debug!("link src = {}", src.display());
-    if !src.exists() || !src.is_file() {
-        warn!("src invalid: {}. exists = {}", src.display(), src.exists());
-        bail!("src file not exists or invalid file")
+    let target = match torrent_info.kind.parse::<ShowKind>()? {
+        ShowKind::Batch => link_batch(&src, torrent_info, config).map(|_| None),
+        ShowKind::Episode | ShowKind::Special | ShowKind::Movie => {
+            link_file(&src, torrent_info, config, &old_links).map(Some)
+        }
+    }?;
+    // 新的链接成功后才删掉旧版本的链接
+    for path in old_links.iter().filter(|p| Some(*p) != target.as_ref()) {
+        info!("unlink replaced {}", path.display());
+        std::fs::remove_file(path)?;
     }
-    let ext = src
-        .extension()
+    // 对账时不再链接旧版本
+    if let Some(old) = &replaced {
+        db::TorrentInfo::set_state(old.id, db::TorrentState::Replaced, None, pool).await?;
+    }
+    if torrent_info.hash.is_none() {
+        db::TorrentInfo::set_hash(torrent_info.id, &torrent.hash, pool).await?;
+    }
+
+    let delete_replaced = config
+        .feed
+        .iter()
+        .find(|f| f.name() == torrent_info.feed)
+        .is_some_and(|f| f.base().delete_replaced());
+    if let Some(old) = replaced.filter(|_| delete_replaced) {
+        // 新的已经链接好了，删除失败不影响
+        if let Err(e) = delete_replaced_torrent(&old, config, session).await {
+            warn!("delete replaced torrent tid{} failed: {e:#}", old.id);
+        }
+    }
+    Ok(())
+}
+
+/// 旧种子可能来自另一个 feed 的下载器
+async fn delete_replaced_torrent(
+    old: &db::TorrentInfo,
+    config: &Config,
+    session: &download::Session,
+) -> Result<()> {
+    let (name, client) = config.feed_client(&old.feed)?;
+    let old_downloader = session.get(name, client).await?;
+    match find_torrent(&old_downloader, old).await? {
+        Some(t) => {
+            old_downloader.delete(&[&t.hash], true).await?;
+            info!(
+                "replaced torrent tid{} deleted from {}",
+                old.id,
+                old_downloader.name()
+            );
+        }
+        None => warn!("replaced torrent tid{} not found", old.id),
+    }
+    Ok(())
+}
+
+const LINK_EXTS: &[&str] = &["mkv", "mp4", "avi", "ts", "m2ts", "ass", "ssa", "srt"];
+
+fn ext(src: &Path) -> Result<&str> {
+    src.extension()
         .context("no extension")?
         .to_str()
-        .context("invalid ext")?;
-    let mut target = config.link_to.clone();
-    // https://emby.media/support/articles/TV-Naming.html
-    let show = if torrent_info.tmdb_id != 0 {
-        format!(
-            "{} ({}) [tmdbid={}]",
-            torrent_info.name, torrent_info.year, torrent_info.tmdb_id
-        )
+        .context("invalid ext")
+}
+
+/// `名字 (年份)`，未匹配 TMDB 时没有年份
+fn title(info: &db::TorrentInfo) -> String {
+    if info.year != 0 {
+        format!("{} ({})", info.name, info.year)
     } else {
-        format!("{} ({})", torrent_info.name, torrent_info.year)
+        info.name.clone()
+    }
+}
+
+/// https://emby.media/support/articles/TV-Naming.html
+fn show_dir(root: &Path, info: &db::TorrentInfo) -> PathBuf {
+    let show = if info.tmdb_id != 0 {
+        format!("{} [tmdbid={}]", title(info), info.tmdb_id)
+    } else {
+        title(info)
     };
-    target.push(show);
-    target.push(format!("Season {}", torrent_info.season));
-    target.push(format!(
-        "{} - S{:02}E{:02} - {}-{}.{}",
-        torrent_info.name,
-        torrent_info.season,
-        torrent_info.episode,
-        torrent_info.fansub,
-        torrent_info.language,
-        ext
-    ));
+    root.join(show)
+}
+
+/// 是否为同一个文件的硬链接
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (std::fs::metadata(a), std::fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (std::fs::metadata(a), std::fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.len() == b.len(),
+        _ => false,
+    }
+}
+
+/// 已经链接过时跳过
+fn link(src: &Path, target: &Path) -> Result<()> {
+    if same_file(src, target) {
+        debug!("{} already linked", target.display());
+        return Ok(());
+    }
     info!("link {} => {}", src.display(), target.display());
     std::fs::create_dir_all(target.parent().unwrap())?;
     std::fs::hard_link(src, target).context("link failed")?;
+    Ok(())
+}
 
+fn check_file(src: &Path) -> Result<()> {
+    if !src.exists() || !src.is_file() {
+        warn!("src invalid: {}. exists = {}", src.display(), src.exists());
+        bail!("src file not exists or invalid file")
+    }
+    Ok(())
+}
+
+/// 单集和剧场版链接的目录和不带扩展名的文件名
+fn target_stem(info: &db::TorrentInfo, config: &Config) -> (PathBuf, String) {
+    if info.kind == ShowKind::Movie.as_str() {
+        // https://emby.media/support/articles/Movie-Naming.html
+        let root = config.movie_link_to.as_ref().unwrap_or(&config.link_to);
+        let stem = format!("{} - {}-{}", title(info), info.fansub, info.language);
+        (show_dir(root, info), stem)
+    } else {
+        let dir = show_dir(&config.link_to, info).join(format!("Season {}", info.season));
+        let stem = format!(
+            "{} - {} - {}-{}",
+            info.name,
+            info.episode_label(),
+            info.fansub,
+            info.language
+        );
+        (dir, stem)
+    }
+}
+
+/// 返回链接的路径。和旧版本的链接同名时覆盖，失败时旧链接还在
+fn link_file(
+    src: &Path,
+    info: &db::TorrentInfo,
+    config: &Config,
+    old_links: &[PathBuf],
+) -> Result<PathBuf> {
+    check_file(src)?;
+    let (dir, stem) = target_stem(info, config);
+    let ext = ext(src)?;
+    let target = dir.join(format!("{stem}.{ext}"));
+    if old_links.contains(&target) && !same_file(src, &target) {
+        let tmp = dir.join(format!(".{stem}.{ext}.tmp"));
+        std::fs::remove_file(&tmp).ok();
+        link(src, &tmp)?;
+        info!("replace {} with {}", target.display(), src.display());
+        std::fs::rename(&tmp, &target)?;
+    } else {
+        link(src, &target)?;
+    }
+    Ok(target)
+}
+
+/// 单集和剧场版已有的链接，不区分扩展名
+fn linked_files(info: &db::TorrentInfo, config: &Config) -> Result<Vec<PathBuf>> {
+    let (dir, stem) = target_stem(info, config);
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+        return Ok(vec![]);
+    };
+    let prefix = format!("{stem}.");
+    let mut files = vec![];
+    for entry in entries {
+        let path = entry?.path();
+        let is_linked = path.file_name().and_then(|n| n.to_str()).is_some_and(|n| {
+            n.strip_prefix(&prefix)
+                .is_some_and(|ext| !ext.contains('.'))
+        });
+        if is_linked {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// 媒体库里是否还有这个种子的链接，合集只检查季目录是否为空
+pub(crate) fn link_exists(info: &db::TorrentInfo, config: &Config) -> Result<bool> {
+    if info.kind == ShowKind::Batch.as_str() {
+        let season_dir = show_dir(&config.link_to, info).join(format!("Season {}", info.season));
+        return Ok(std::fs::read_dir(season_dir).is_ok_and(|mut d| d.next().is_some()));
+    }
+    Ok(!linked_files(info, config)?.is_empty())
+}
+
+/// 合集逐个文件识别集数后链接，识别不出的保留原文件名
+fn link_batch(src: &Path, info: &db::TorrentInfo, config: &Config) -> Result<()> {
+    if !src.exists() {
+        bail!("src not exists: {}", src.display());
+    }
+    let season_dir = show_dir(&config.link_to, info).join(format!("Season {}", info.season));
+    let mut stack = vec![src.to_path_buf()];
+    let mut linked = 0;
+    while let Some(path) = stack.pop() {
+        if path.is_dir() {
+            for entry in std::fs::read_dir(&path)? {
+                stack.push(entry?.path());
+            }
+            continue;
+        }
+        let Ok(ext) = ext(&path) else {
+            continue;
+        };
+        if !LINK_EXTS.contains(&ext.to_lowercase().as_str()) {
+            continue;
+        }
+        let stem = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .context("invalid file name")?;
+        // 字幕文件保留 `.sc.ass` 这样的语言后缀
+        let (stem, sub_lang) = match stem.rsplit_once('.') {
+            Some((stem, lang)) if lang.len() <= 5 && !lang.contains(' ') => {
+                (stem, format!(".{lang}"))
+            }
+            _ => (stem, String::new()),
+        };
+        let file_name = match crate::parser::parse(stem) {
+            Recognized::Show(ep) => format!(
+                "{} - S{:02}E{:02} - {}-{}{sub_lang}.{ext}",
+                info.name, info.season, ep.episode, info.fansub, info.language
+            ),
+            Recognized::Other => path.file_name().unwrap().to_string_lossy().to_string(),
+        };
+        let target = season_dir.join(file_name);
+        if same_file(&path, &target) {
+            continue;
+        }
+        if target.exists() {
+            warn!("target {} exists, skip", target.display());
+            continue;
+        }
+        link(&path, &target)?;
+        linked += 1;
+    }
+    info!("{linked} files linked for batch {}", info.name);
     Ok(())
 }
 
 #[post("/qb_hook")]
-async fn hello(body: bytes::Bytes) -> impl Responder {
-    match on_hook(body).await {
+async fn hello(body: bytes::Bytes, session: web::Data<download::Session>) -> impl Responder {
+    match on_hook(body, &session).await {
         Ok(_) => {
             info!("hook run success!");
             HttpResponse::Ok().body("ok")
@@ -99,10 +394,124 @@ async fn hello(body: bytes::Bytes) -> impl Responder {
     }
 }
 
-pub async fn main() -> Result<()> {
-    HttpServer::new(|| App::new().service(hello))
-        .bind(("0.0.0.0", 80))?
-        .run()
-        .await?;
+/// 按 feed 和月份汇总的 GPT 用量
+#[get("/gpt_usage")]
+async fn gpt_usage() -> impl Responder {
+    let r = async {
+        let (_, pool) = load().await?;
+        db::GptUsage::totals(&pool).await
+    };
+    match r.await {
+        Ok(totals) => HttpResponse::Ok().json(totals),
+        Err(e) => {
+            error!("get gpt usage failed! err = {e:#?}");
+            HttpResponse::InternalServerError().body(format!("{e:#?}"))
+        }
+    }
+}
+
+/// 失败的种子及其错误信息
+#[derive(serde::Serialize)]
+struct FailedTorrent {
+    tid: i64,
+    name: String,
+    episode: String,
+    feed: String,
+    error: Option<String>,
+    updated_at: i64,
+}
+
+/// 添加或链接失败的种子
+#[get("/torrents/failed")]
+async fn torrents_failed() -> impl Responder {
+    let r = async {
+        let (_, pool) = load().await?;
+        let failed = db::TorrentInfo::failed(&pool).await?;
+        anyhow::Ok(
+            failed
+                .into_iter()
+                .map(|t| FailedTorrent {
+                    tid: t.id,
+                    episode: t.episode_label(),
+                    name: t.name,
+                    feed: t.feed,
+                    error: t.error,
+                    updated_at: t.updated_at,
+                })
+                .collect::<Vec<_>>(),
+        )
+    };
+    match r.await {
+        Ok(failed) => HttpResponse::Ok().json(failed),
+        Err(e) => {
+            error!("get failed torrents failed! err = {e:#?}");
+            HttpResponse::InternalServerError().body(format!("{e:#?}"))
+        }
+    }
+}
+
+/// TMDB 上没有找到的剧名
+#[get("/tmdb/unmatched")]
+async fn tmdb_unmatched() -> impl Responder {
+    let r = async {
+        let (_, pool) = load().await?;
+        db::TmdbShow::list_unmatched(&pool).await
+    };
+    match r.await {
+        Ok(names) => HttpResponse::Ok().json(names),
+        Err(e) => {
+            error!("get unmatched tmdb names failed! err = {e:#?}");
+            HttpResponse::InternalServerError().body(format!("{e:#?}"))
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct Invalidate {
+    name: Option<String>,
+    tmdb_id: Option<i64>,
+}
+
+/// 删除某个剧名或 TMDB 条目的缓存，下次刷新时重新搜索
+#[post("/tmdb/invalidate")]
+async fn tmdb_invalidate(req: web::Json<Invalidate>) -> impl Responder {
+    let r = async {
+        let (_, pool) = load().await?;
+        let mut n = 0;
+        if let Some(name) = &req.name {
+            n += db::TmdbShow::invalidate_name(name, &pool).await?;
+        }
+        if let Some(tmdb_id) = req.tmdb_id {
+            n += db::TmdbShow::invalidate_id(tmdb_id, &pool).await?;
+        }
+        anyhow::Ok(n)
+    };
+    match r.await {
+        Ok(n) => {
+            info!("{n} tmdb cache entries invalidated");
+            HttpResponse::Ok().body(format!("{n} invalidated"))
+        }
+        Err(e) => {
+            error!("invalidate tmdb cache failed! err = {e:#?}");
+            HttpResponse::InternalServerError().body(format!("{e:#?}"))
+        }
+    }
+}
+
+/// `session` 与 runner 共用
+pub async fn main(session: Arc<download::Session>) -> Result<()> {
+    let session = web::Data::from(session);
+    HttpServer::new(move || {
+        App::new()
+            .app_data(session.clone())
+            .service(hello)
+            .service(gpt_usage)
+            .service(tmdb_unmatched)
+            .service(tmdb_invalidate)
+            .service(torrents_failed)
+    })
+    .bind(("0.0.0.0", 80))?
+    .run()
+    .await?;
     Ok(())
 }
@@ -0,0 +1,313 @@
+//! 种子文件的解析，https://www.bittorrent.org/beps/bep_0003.html
+use anyhow::{bail, Context, Result};
+use sha1::Digest;
+
+/// 种子里的一个文件，`path` 以 `/` 分隔
+#[derive(Debug, Clone)]
+pub struct File {
+    pub path: String,
+    pub length: u64,
+}
+
+impl File {
+    /// 小写的扩展名
+    pub fn ext(&self) -> Option<String> {
+        let name = self.path.rsplit('/').next()?;
+        let (_, ext) = name.rsplit_once('.')?;
+        Some(ext.to_lowercase())
+    }
+}
+
+#[derive(Debug)]
+pub struct Metainfo {
+    pub name: String,
+    pub files: Vec<File>,
+    /// v1 infohash，小写十六进制
+    pub info_hash: String,
+}
+
+impl Metainfo {
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let root = Parser::new(bytes).parse()?;
+        let info = root.get(b"info").context("no info in torrent")?;
+        let Value::Dict(_, raw) = info else {
+            bail!("invalid info in torrent");
+        };
+        let info_hash = hex(&sha1::Sha1::digest(raw));
+        let name = info
+            .get(b"name.utf-8")
+            .or_else(|| info.get(b"name"))
+            .and_then(Value::as_str)
+            .context("no name in torrent")?
+            .to_string();
+        let files = match info.get(b"files") {
+            // 多文件
+            Some(Value::List(files)) => files
+                .iter()
+                .map(|f| {
+                    let length = f
+                        .get(b"length")
+                        .and_then(Value::as_int)
+                        .context("no length")?;
+                    let Some(Value::List(path)) = f.get(b"path.utf-8").or_else(|| f.get(b"path"))
+                    else {
+                        bail!("no path");
+                    };
+                    let path = path
+                        .iter()
+                        .map(|p| p.as_str().context("invalid path"))
+                        .collect::<Result<Vec<_>>>()?
+                        .join("/");
+                    Ok(File {
+                        path,
+                        length: u64::try_from(length).context("negative length")?,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?,
+            Some(_) => bail!("invalid files in torrent"),
+            None => {
+                let length = info
+                    .get(b"length")
+                    .and_then(Value::as_int)
+                    .context("no length in torrent")?;
+                vec![File {
+                    path: name.clone(),
+                    length: u64::try_from(length).context("negative length in torrent")?,
+                }]
+            }
+        };
+        Ok(Self {
+            name,
+            files,
+            info_hash,
+        })
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.files.iter().map(|f| f.length).sum()
+    }
+
+    pub fn largest(&self) -> Option<&File> {
+        self.files.iter().max_by_key(|f| f.length)
+    }
+}
+
+/// 磁力链接中的 infohash，小写十六进制
+pub fn magnet_hash(uri: &str) -> Option<String> {
+    let url = url::Url::parse(uri).ok()?;
+    if url.scheme() != "magnet" {
+        return None;
+    }
+    let (_, xt) = url.query_pairs().find(|(k, _)| k == "xt")?;
+    let hash = xt.strip_prefix("urn:btih:")?;
+    match hash.len() {
+        40 if hash.chars().all(|c| c.is_ascii_hexdigit()) => Some(hash.to_lowercase()),
+        32 => base32(hash).map(|b| hex(&b)),
+        _ => None,
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// RFC 4648 base32，不带填充
+fn base32(s: &str) -> Option<Vec<u8>> {
+    let mut bits = 0u64;
+    let mut n = 0;
+    let mut out = vec![];
+    for c in s.bytes() {
+        let v = match c.to_ascii_uppercase() {
+            c @ b'A'..=b'Z' => c - b'A',
+            c @ b'2'..=b'7' => c - b'2' + 26,
+            _ => return None,
+        };
+        bits = (bits << 5) | v as u64;
+        n += 5;
+        if n >= 8 {
+            n -= 8;
+            out.push((bits >> n) as u8);
+            bits &= (1 << n) - 1;
+        }
+    }
+    Some(out)
+}
+
+/// 下载种子文件
+pub async fn fetch(client: &reqwest::Client, url: &str, cookie: Option<&str>) -> Result<Vec<u8>> {
+    let mut req = client.get(url);
+    if let Some(cookie) = cookie {
+        req = req.header(reqwest::header::COOKIE, cookie);
+    }
+    let r = req
+        .send()
+        .await
+        .with_context(|| format!("download torrent {url} failed"))?
+        .error_for_status()?;
+    Ok(r.bytes().await?.to_vec())
+}
+
+#[derive(Debug)]
+enum Value<'a> {
+    Int(i64),
+    Bytes(&'a [u8]),
+    List(Vec<Value<'a>>),
+    /// 保留原始字节，用于计算 infohash
+    Dict(Vec<(&'a [u8], Value<'a>)>, &'a [u8]),
+}
+
+impl<'a> Value<'a> {
+    fn get(&self, key: &[u8]) -> Option<&Value<'a>> {
+        match self {
+            Value::Dict(d, _) => d.iter().find(|(k, _)| *k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+    fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+    fn as_str(&self) -> Option<&'a str> {
+        match self {
+            Value::Bytes(b) => std::str::from_utf8(b).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// 列表和字典的最大嵌套层数，防止恶意种子爆栈
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self {
+            buf,
+            pos: 0,
+            depth: 0,
+        }
+    }
+
+    fn enter(&mut self) -> Result<()> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            bail!("torrent nested too deep at {}", self.pos);
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn peek(&self) -> Result<u8> {
+        self.buf
+            .get(self.pos)
+            .copied()
+            .context("unexpected end of torrent")
+    }
+
+    /// 读到 `end` 为止，不包括 `end`
+    fn until(&mut self, end: u8) -> Result<&'a str> {
+        let len = self.buf[self.pos..]
+            .iter()
+            .position(|&c| c == end)
+            .context("unexpected end of torrent")?;
+        let s = std::str::from_utf8(&self.buf[self.pos..self.pos + len])?;
+        self.pos += len + 1;
+        Ok(s)
+    }
+
+    fn parse(&mut self) -> Result<Value<'a>> {
+        match self.peek()? {
+            b'i' => {
+                self.pos += 1;
+                Ok(Value::Int(self.until(b'e')?.parse()?))
+            }
+            b'l' => {
+                self.enter()?;
+                let mut list = vec![];
+                while self.peek()? != b'e' {
+                    list.push(self.parse()?);
+                }
+                self.pos += 1;
+                self.depth -= 1;
+                Ok(Value::List(list))
+            }
+            b'd' => {
+                let start = self.pos;
+                self.enter()?;
+                let mut dict = vec![];
+                while self.peek()? != b'e' {
+                    let Value::Bytes(key) = self.parse()? else {
+                        bail!("dict key is not a string");
+                    };
+                    dict.push((key, self.parse()?));
+                }
+                self.pos += 1;
+                self.depth -= 1;
+                Ok(Value::Dict(dict, &self.buf[start..self.pos]))
+            }
+            b'0'..=b'9' => {
+                let len: usize = self.until(b':')?.parse()?;
+                let end = self
+                    .pos
+                    .checked_add(len)
+                    .context("string length overflow")?;
+                let bytes = self
+                    .buf
+                    .get(self.pos..end)
+                    .context("unexpected end of torrent")?;
+                self.pos = end;
+                Ok(Value::Bytes(bytes))
+            }
+            c => bail!("invalid bencode byte {c:#x} at {}", self.pos),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_multi_file() {
+        let meta = Metainfo::parse(include_bytes!("../tests/multi-file.torrent")).unwrap();
+        assert_eq!(
+            meta.name,
+            "[LoliHouse] 孤独摇滚 - 01 [WebRip 1080p HEVC-10bit AAC]"
+        );
+        assert_eq!(meta.files.len(), 2);
+        assert_eq!(meta.files[1].path, "Fonts/readme.txt");
+        assert_eq!(meta.total_size(), 734003200 + 1024);
+        assert_eq!(meta.largest().unwrap().ext().as_deref(), Some("mkv"));
+        assert_eq!(meta.info_hash, "0631a44a87be60ee5a5a23ce416aabc902156320");
+    }
+
+    #[test]
+    fn magnet() {
+        assert_eq!(
+            magnet_hash("magnet:?xt=urn:btih:C12FE1C06BBA254A9DC9F519B335AA7C1367A88A&dn=test")
+                .as_deref(),
+            Some("c12fe1c06bba254a9dc9f519b335aa7c1367a88a")
+        );
+        assert_eq!(
+            magnet_hash("magnet:?xt=urn:btih:YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKEK").as_deref(),
+            Some("c12fe1c06bba254a9dc9f519b335aa7c1367a88a")
+        );
+        assert_eq!(magnet_hash("https://example.com/a.torrent"), None);
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(Metainfo::parse(b"d4:infoi1ee").is_err());
+        assert!(Metainfo::parse(b"d4:info").is_err());
+        assert!(Metainfo::parse(b"d4:infod4:name1:a6:lengthi-1eee").is_err());
+        assert!(Metainfo::parse(b"d4:info18446744073709551615:").is_err());
+        assert!(Parser::new(&[b'l'; 100_000]).parse().is_err());
+    }
+}